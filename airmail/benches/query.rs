@@ -0,0 +1,72 @@
+//! Search latency by query length. To compare a change, run
+//! `cargo bench -p airmail --bench query -- --save-baseline before` without it, then
+//! `cargo bench -p airmail --bench query -- --baseline before` with it.
+
+use airmail::{
+    index::AirmailIndex,
+    poi::{SchemafiedPoi, ToIndexPoi},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lingua::Language;
+
+const STREETS: [&str; 8] = [
+    "Fremont Ave N",
+    "Pennsylvania Avenue Northwest",
+    "Rue Sainte-Catherine",
+    "Carrer de Villarroel",
+    "Fifth Avenue",
+    "Calle Diez",
+    "Main Street",
+    "Broadway",
+];
+
+const QUERIES: [&str; 4] = [
+    "fremont ave",
+    "123 fremont ave n seattle",
+    "1600 pennsylvania avenue northwest, washington district of columbia 20500",
+    "1600 pennsylvania avenue northwest, washington district of columbia 20500 united states of america",
+];
+
+/// Build a small index of synthetic addresses to run queries against.
+fn build_index(dir: &std::path::Path) -> AirmailIndex {
+    let mut index = AirmailIndex::create(dir).unwrap();
+    let mut writer = index.writer().unwrap();
+    for (i, street) in STREETS.iter().cycle().take(2000).enumerate() {
+        let mut poi = ToIndexPoi::new(
+            vec![],
+            Some(format!("{}", i % 250 + 1)),
+            Some(street.to_string()),
+            None,
+            40.0 + (i as f64) / 1000.0,
+            -100.0 + (i as f64) / 1000.0,
+            vec![("addr:street".to_string(), street.to_string())],
+        )
+        .unwrap();
+        poi.languages = vec![Language::English];
+        writer.add_poi(SchemafiedPoi::from(poi), "bench").unwrap();
+    }
+    writer.commit().unwrap();
+    AirmailIndex::new(dir.to_str().unwrap()).unwrap()
+}
+
+fn bench_long_queries(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let index = build_index(dir.path());
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("search");
+    for query in QUERIES {
+        let tokens = query.split_whitespace().count();
+        for lenient in [false, true] {
+            let id = format!("{} tokens, lenient={}", tokens, lenient);
+            group.bench_with_input(BenchmarkId::from_parameter(id), &query, |b, query| {
+                b.to_async(&runtime)
+                    .iter(|| index.search(query, lenient, None, None, &[]));
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_long_queries);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+use std::{env, fs, path::Path};
+
+/// Embed every libpostal dictionary under `dictionaries/` and every number table under
+/// `numbers/` into the binary, so the registry in `src/dictionaries.rs` can look them up by
+/// language and category without a directory listing of its own to keep in sync.
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let dictionaries_dir = Path::new(&manifest_dir).join("dictionaries");
+    println!("cargo:rerun-if-changed={}", dictionaries_dir.display());
+
+    let mut entries = Vec::new();
+    for language_dir in fs::read_dir(&dictionaries_dir).unwrap() {
+        let language_dir = language_dir.unwrap().path();
+        if !language_dir.is_dir() {
+            continue;
+        }
+        let language = language_dir
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        for file in fs::read_dir(&language_dir).unwrap() {
+            let file = file.unwrap().path();
+            if file.extension().is_some_and(|ext| ext == "txt") {
+                let category = file.file_stem().unwrap().to_string_lossy().to_string();
+                entries.push((language.clone(), category, file.display().to_string()));
+            }
+        }
+    }
+    entries.sort();
+
+    let mut generated =
+        String::from("pub(crate) static DICTIONARY_FILES: &[(&str, &str, &str)] = &[\n");
+    for (language, category, path) in entries {
+        generated.push_str(&format!(
+            "    ({:?}, {:?}, include_str!({:?})),\n",
+            language, category, path
+        ));
+    }
+    generated.push_str("];\n");
+
+    let numbers_dir = Path::new(&manifest_dir).join("numbers");
+    println!("cargo:rerun-if-changed={}", numbers_dir.display());
+    let mut number_files = Vec::new();
+    for file in fs::read_dir(&numbers_dir).unwrap() {
+        let file = file.unwrap().path();
+        if file.extension().is_some_and(|ext| ext == "txt") {
+            let language = file.file_stem().unwrap().to_string_lossy().to_string();
+            number_files.push((language, file.display().to_string()));
+        }
+    }
+    number_files.sort();
+
+    generated.push_str("\npub(crate) static NUMBER_FILES: &[(&str, &str)] = &[\n");
+    for (language, path) in number_files {
+        generated.push_str(&format!(
+            "    ({:?}, include_str!({:?})),\n",
+            language, path
+        ));
+    }
+    generated.push_str("];\n");
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("dictionary_files.rs");
+    fs::write(out_path, generated).unwrap();
+}
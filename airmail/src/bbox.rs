@@ -0,0 +1,157 @@
+use geo::{Coord, Intersects, Point, Rect};
+
+use crate::error::AirmailError;
+
+fn check_range(name: &'static str, value: f64, limit: f64) -> Result<f64, AirmailError> {
+    if (-limit..=limit).contains(&value) {
+        Ok(value)
+    } else {
+        Err(AirmailError::CoordinateOutOfRange { name, value, limit })
+    }
+}
+
+/// An area searched, which unlike a [`Rect`] may cross the antimeridian, like Fiji's
+/// `177,-19,-178,-16`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bbox {
+    min_lng: f64,
+    min_lat: f64,
+    max_lng: f64,
+    max_lat: f64,
+}
+
+impl Bbox {
+    /// Whether the bbox's western edge is east of its eastern edge.
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.min_lng > self.max_lng
+    }
+
+    /// The bbox as rects that don't cross the antimeridian: itself, or its parts either side.
+    pub fn rects(&self) -> Vec<Rect> {
+        let rect = |min_lng, max_lng| {
+            Rect::new(
+                Coord {
+                    y: self.min_lat,
+                    x: min_lng,
+                },
+                Coord {
+                    y: self.max_lat,
+                    x: max_lng,
+                },
+            )
+        };
+        if self.crosses_antimeridian() {
+            vec![rect(self.min_lng, 180.0), rect(-180.0, self.max_lng)]
+        } else {
+            vec![rect(self.min_lng, self.max_lng)]
+        }
+    }
+
+    /// The middle of the bbox, which for one crossing the antimeridian is near it.
+    pub fn center(&self) -> Point {
+        let mut lng = (self.min_lng + self.max_lng) / 2.0;
+        if self.crosses_antimeridian() {
+            lng += if lng > 0.0 { -180.0 } else { 180.0 };
+        }
+        Point::new(lng, (self.min_lat + self.max_lat) / 2.0)
+    }
+
+    pub fn intersects(&self, other: &Bbox) -> bool {
+        self.rects()
+            .iter()
+            .any(|rect| other.rects().iter().any(|other| rect.intersects(other)))
+    }
+}
+
+impl From<Rect> for Bbox {
+    fn from(rect: Rect) -> Self {
+        Self {
+            min_lng: rect.min().x,
+            min_lat: rect.min().y,
+            max_lng: rect.max().x,
+            max_lat: rect.max().y,
+        }
+    }
+}
+
+/// Parse a bbox of the form `min_lng,min_lat,max_lng,max_lat`. A `min_lng` greater than
+/// `max_lng` means the bbox crosses the antimeridian.
+pub fn parse_bbox(s: &str) -> Result<Bbox, AirmailError> {
+    let invalid = |reason: &str| AirmailError::InvalidBbox(format!("{s}: {reason}"));
+    let parts = s
+        .split(',')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid("coordinates must be numbers"))?;
+    let [min_lng, min_lat, max_lng, max_lat] = parts[..] else {
+        return Err(invalid("expected min_lng,min_lat,max_lng,max_lat"));
+    };
+    let min_lng = check_range("longitude", min_lng, 180.0)?;
+    let min_lat = check_range("latitude", min_lat, 90.0)?;
+    let max_lng = check_range("longitude", max_lng, 180.0)?;
+    let max_lat = check_range("latitude", max_lat, 90.0)?;
+    if min_lat > max_lat {
+        return Err(invalid("min_lat must not be greater than max_lat"));
+    }
+
+    Ok(Bbox {
+        min_lng,
+        min_lat,
+        max_lng,
+        max_lat,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::AirmailError;
+
+    use super::parse_bbox;
+
+    #[test]
+    fn test_parse_bbox() {
+        let bbox = parse_bbox("-122.5,47.4,-122.2,47.8").unwrap();
+        assert!(!bbox.crosses_antimeridian());
+        assert_eq!(bbox.rects().len(), 1);
+        assert_eq!(
+            (bbox.rects()[0].min().x, bbox.rects()[0].max().y),
+            (-122.5, 47.8)
+        );
+        assert!(matches!(
+            parse_bbox("-122.5,47.4,-122.2"),
+            Err(AirmailError::InvalidBbox(_))
+        ));
+        assert!(matches!(
+            parse_bbox("a,b,c,d"),
+            Err(AirmailError::InvalidBbox(_))
+        ));
+        assert!(matches!(
+            parse_bbox("-122.5,47.8,-122.2,47.4"),
+            Err(AirmailError::InvalidBbox(_))
+        ));
+        assert!(matches!(
+            parse_bbox("-122.5,47.4,-122.2,91"),
+            Err(AirmailError::CoordinateOutOfRange {
+                name: "latitude",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_antimeridian_bbox() {
+        let fiji = parse_bbox("177,-19,-178,-16").unwrap();
+        assert!(fiji.crosses_antimeridian());
+        let rects = fiji.rects();
+        assert_eq!((rects[0].min().x, rects[0].max().x), (177.0, 180.0));
+        assert_eq!((rects[1].min().x, rects[1].max().x), (-180.0, -178.0));
+        assert_eq!(fiji.center().x(), 179.5);
+        assert_eq!(parse_bbox("178,-19,-177,-16").unwrap().center().x(), -179.5);
+
+        let suva = parse_bbox("178.3,-18.2,178.6,-18").unwrap();
+        let seattle = parse_bbox("-122.5,47.4,-122.2,47.8").unwrap();
+        assert!(fiji.intersects(&suva));
+        assert!(suva.intersects(&fiji));
+        assert!(!fiji.intersects(&seattle));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashSet;
+
+use geo::{HaversineDistance, Point};
+use lingua::Language;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parser::{tokenize, AddressComponent, AddressLabel},
+    poi::AirmailPoi,
+    query::{expand_token, number_languages},
+    substitutions::normalize_numbers,
+};
+
+/// How much of the query has to match a result's street for it to count as a street match.
+const STREET_MATCH_THRESHOLD: f64 = 0.5;
+
+/// Weights of the signals making up confidence. Signals for parts of an address the query
+/// doesn't have are left out, and the rest are reweighted.
+const COVERAGE_WEIGHT: f64 = 0.4;
+const HOUSE_NUMBER_WEIGHT: f64 = 0.25;
+const STREET_WEIGHT: f64 = 0.2;
+const ADMIN_WEIGHT: f64 = 0.15;
+
+/// The most confidence a result can lose to being far from the focus point.
+const FOCUS_WEIGHT: f64 = 0.25;
+/// The distance from the focus point at which half of [`FOCUS_WEIGHT`] is lost.
+const FOCUS_SCALE_KM: f64 = 10.0;
+
+/// How a result matched its query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchType {
+    /// The house number and street matched, or every word of a place's name.
+    Exact,
+    /// Like [`MatchType::Exact`], but the address was interpolated along the street.
+    Interpolated,
+    /// The street matched, but not the house number.
+    Street,
+    /// Only the city, region or other admin area matched, or the result is one.
+    Locality,
+    /// The result only shares some words with the query.
+    #[default]
+    Fallback,
+}
+
+/// The words of some text, along with their abbreviations and expansions, and the words of the
+/// text with its numbers normalized like a query's would be.
+struct Words<'a> {
+    words: HashSet<String>,
+    languages: &'a [Language],
+}
+
+impl<'a> Words<'a> {
+    fn of<'b>(texts: impl Iterator<Item = &'b str>, languages: &'a [Language]) -> Self {
+        let mut words = HashSet::new();
+        for text in texts {
+            for token in tokenize(text) {
+                words.extend(expand_token(&token, languages));
+                words.insert(token);
+            }
+            for language in number_languages(languages) {
+                words.extend(tokenize(&normalize_numbers(text, &language)));
+            }
+        }
+        Self { words, languages }
+    }
+
+    fn covers(&self, token: &str) -> bool {
+        self.words.contains(token)
+            || expand_token(token, self.languages)
+                .iter()
+                .any(|alternative| self.words.contains(alternative))
+    }
+}
+
+fn tag<'a>(poi: &'a AirmailPoi, key: &str) -> Option<&'a str> {
+    poi.tags
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Tags whose values a query might mention: names and address parts.
+fn is_text_tag(key: &str) -> bool {
+    key == "name" || key.starts_with("name:") || key.ends_with("_name") || key.starts_with("addr:")
+}
+
+fn is_admin(label: AddressLabel) -> bool {
+    matches!(
+        label,
+        AddressLabel::Locality
+            | AddressLabel::Region
+            | AddressLabel::Postcode
+            | AddressLabel::Country
+    )
+}
+
+fn fraction(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f64 / total as f64
+}
+
+/// Judge how well a result matches the query it was found for, which was parsed into
+/// `components`, returning a confidence from 0 to 1 and how it matched.
+///
+/// Localities and regions in the query are also matched against the admin areas the result is
+/// in, which its tags often leave out. `languages` are the ones the query might be in, for
+/// matching numbers. `focus` is where results are expected to be, if anywhere, like the middle of
+/// the area searched.
+pub fn assess(
+    components: &[AddressComponent],
+    languages: &[Language],
+    poi: &AirmailPoi,
+    focus: Option<Point>,
+) -> (f64, MatchType) {
+    let words = Words::of(
+        poi.tags
+            .iter()
+            .filter(|(key, _)| is_text_tag(key))
+            .map(|(_, value)| value.as_str()),
+        languages,
+    );
+    let admin_words = Words::of(poi.admins.iter().map(String::as_str), languages);
+    let tokens: Vec<(AddressLabel, &str)> = components
+        .iter()
+        .flat_map(|component| {
+            component
+                .tokens
+                .iter()
+                .filter(|token| token.chars().any(char::is_alphanumeric))
+                .map(|token| (component.label, token.as_str()))
+        })
+        .collect();
+    if tokens.is_empty() {
+        return (0.0, MatchType::Fallback);
+    }
+    let covered = |label: AddressLabel, token: &str| {
+        words.covers(token) || (is_admin(label) && admin_words.covers(token))
+    };
+    let coverage_of = |labels: &dyn Fn(AddressLabel) -> bool| {
+        let tokens: Vec<_> = tokens.iter().filter(|(label, _)| labels(*label)).collect();
+        (!tokens.is_empty()).then(|| {
+            fraction(
+                tokens
+                    .iter()
+                    .filter(|(label, token)| covered(*label, token))
+                    .count(),
+                tokens.len(),
+            )
+        })
+    };
+
+    let coverage = coverage_of(&|_| true).unwrap_or_default();
+    let house_number_matched = components
+        .iter()
+        .find(|component| component.label == AddressLabel::HouseNumber)
+        .map(|component| {
+            tag(poi, "addr:housenumber").is_some_and(|number| {
+                number.replace(' ', "").to_lowercase() == component.tokens.concat()
+            })
+        });
+    let street = tag(poi, "addr:street").or_else(|| tag(poi, "name"));
+    let street_words = Words::of(street.into_iter(), languages);
+    let road: Vec<&str> = tokens
+        .iter()
+        .filter(|(label, _)| *label == AddressLabel::Road)
+        .map(|(_, token)| *token)
+        .collect();
+    let street_match = (!road.is_empty()).then(|| {
+        fraction(
+            road.iter()
+                .filter(|token| street_words.covers(token))
+                .count(),
+            road.len(),
+        )
+    });
+    let admin_match = coverage_of(&is_admin);
+
+    let signals = [
+        Some((coverage, COVERAGE_WEIGHT)),
+        house_number_matched.map(|matched| (f64::from(u8::from(matched)), HOUSE_NUMBER_WEIGHT)),
+        street_match.map(|street_match| (street_match, STREET_WEIGHT)),
+        admin_match.map(|admin_match| (admin_match, ADMIN_WEIGHT)),
+    ];
+    let (total, weights) = signals
+        .iter()
+        .flatten()
+        .fold((0.0, 0.0), |(total, weights), (signal, weight)| {
+            (total + signal * weight, weights + weight)
+        });
+    let mut confidence = total / weights;
+    if let Some(focus) = focus {
+        let km = focus.haversine_distance(&Point::new(poi.lng, poi.lat)) / 1000.0;
+        confidence *= 1.0 - FOCUS_WEIGHT * km / (km + FOCUS_SCALE_KM);
+    }
+
+    let street_matched =
+        street_match.is_some_and(|street_match| street_match >= STREET_MATCH_THRESHOLD);
+    let name_matched = coverage_of(&|label| label == AddressLabel::Name) == Some(1.0);
+    let is_place = poi
+        .tags
+        .iter()
+        .any(|(key, _)| key == "place" || key == "boundary");
+    let match_type = if house_number_matched == Some(true) && (road.is_empty() || street_matched) {
+        if poi.interpolated {
+            MatchType::Interpolated
+        } else {
+            MatchType::Exact
+        }
+    } else if house_number_matched.is_none() && name_matched && coverage >= 1.0 {
+        MatchType::Exact
+    } else if street_matched {
+        MatchType::Street
+    } else if is_place || admin_match.is_some_and(|admin_match| admin_match > 0.0) {
+        MatchType::Locality
+    } else {
+        MatchType::Fallback
+    };
+
+    (confidence.clamp(0.0, 1.0), match_type)
+}
+
+#[cfg(test)]
+mod test {
+    use geo::Point;
+
+    use super::{assess, MatchType};
+    use crate::{parser::parse, poi::AirmailPoi};
+
+    fn poi(tags: &[(&str, &str)]) -> AirmailPoi {
+        let tags = tags
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        AirmailPoi::new("test".to_string(), 47.6, -122.3, tags).unwrap()
+    }
+
+    #[test]
+    fn test_assess() {
+        let address = poi(&[
+            ("addr:housenumber", "123"),
+            ("addr:street", "Fremont Avenue North"),
+            ("addr:city", "Seattle"),
+        ]);
+        let components = parse("123 fremont ave n seattle", |_| false);
+        let (exact, match_type) = assess(&components, &[], &address, None);
+        assert_eq!(match_type, MatchType::Exact);
+        assert!(exact > 0.95, "{}", exact);
+
+        let components = parse("125 fremont ave n seattle", |_| false);
+        let (street, match_type) = assess(&components, &[], &address, None);
+        assert_eq!(match_type, MatchType::Street);
+        assert!(street < exact);
+
+        let city = poi(&[("place", "city"), ("name", "Seattle")]);
+        let components = parse("999 nowhere rd seattle", |_| false);
+        let (locality, match_type) = assess(&components, &[], &city, None);
+        assert_eq!(match_type, MatchType::Locality);
+        assert!(locality < street);
+
+        let cafe = poi(&[("amenity", "cafe"), ("name", "Lighthouse Cafe")]);
+        let components = parse("harbor cafe", |_| false);
+        assert_eq!(assess(&components, &[], &cafe, None).1, MatchType::Fallback);
+
+        let components = parse("lighthouse cafe", |_| false);
+        let (near, _) = assess(&components, &[], &cafe, Some(Point::new(-122.3, 47.6)));
+        let (far, _) = assess(&components, &[], &cafe, Some(Point::new(-73.9, 40.7)));
+        assert!(near > far);
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use itertools::Itertools;
+use lingua::Language;
+use log::{info, warn};
+
+use crate::substitutions::SubstitutionDict;
+
+// Generated by build.rs: `(language, category, contents)` for every file in `dictionaries/`, and
+// `(language, contents)` for every file in `numbers/`.
+include!(concat!(env!("OUT_DIR"), "/dictionary_files.rs"));
+
+/// Dictionaries in this directory apply to every language.
+const ALL_LANGUAGES: &str = "all";
+
+/// Pseudo language code for the union of every known language's dictionaries, used where the
+/// language of the text isn't known, e.g. in queries.
+pub const ANY_LANGUAGE: &str = "*";
+
+/// The kinds of libpostal dictionaries, named after their file stems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DictionaryCategory {
+    AcademicDegrees,
+    AmbiguousExpansions,
+    BuildingTypes,
+    Chains,
+    CompanyTypes,
+    ConcatenatedPrefixesSeparable,
+    ConcatenatedSuffixesInseparable,
+    ConcatenatedSuffixesSeparable,
+    CrossStreets,
+    Directionals,
+    Elisions,
+    Entrances,
+    GivenNames,
+    HouseNumbers,
+    LevelTypesBasement,
+    LevelTypesMezzanine,
+    LevelTypesNumbered,
+    LevelTypesStandalone,
+    LevelTypesSubBasement,
+    Near,
+    NoNumber,
+    Nulls,
+    Number,
+    People,
+    PersonalSuffixes,
+    PersonalTitles,
+    PlaceNames,
+    PostOffice,
+    Postcodes,
+    Qualifiers,
+    Staircases,
+    Stopwords,
+    StreetNames,
+    StreetTypes,
+    Surnames,
+    Synonyms,
+    Toponyms,
+    UnitDirections,
+    UnitTypesNumbered,
+    UnitTypesStandalone,
+}
+
+impl DictionaryCategory {
+    pub const ALL: [Self; 40] = [
+        Self::AcademicDegrees,
+        Self::AmbiguousExpansions,
+        Self::BuildingTypes,
+        Self::Chains,
+        Self::CompanyTypes,
+        Self::ConcatenatedPrefixesSeparable,
+        Self::ConcatenatedSuffixesInseparable,
+        Self::ConcatenatedSuffixesSeparable,
+        Self::CrossStreets,
+        Self::Directionals,
+        Self::Elisions,
+        Self::Entrances,
+        Self::GivenNames,
+        Self::HouseNumbers,
+        Self::LevelTypesBasement,
+        Self::LevelTypesMezzanine,
+        Self::LevelTypesNumbered,
+        Self::LevelTypesStandalone,
+        Self::LevelTypesSubBasement,
+        Self::Near,
+        Self::NoNumber,
+        Self::Nulls,
+        Self::Number,
+        Self::People,
+        Self::PersonalSuffixes,
+        Self::PersonalTitles,
+        Self::PlaceNames,
+        Self::PostOffice,
+        Self::Postcodes,
+        Self::Qualifiers,
+        Self::Staircases,
+        Self::Stopwords,
+        Self::StreetNames,
+        Self::StreetTypes,
+        Self::Surnames,
+        Self::Synonyms,
+        Self::Toponyms,
+        Self::UnitDirections,
+        Self::UnitTypesNumbered,
+        Self::UnitTypesStandalone,
+    ];
+
+    /// The category whose dictionary files are named `<stem>.txt`, if any.
+    pub fn from_file_stem(stem: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.file_stem() == stem)
+    }
+
+    pub fn file_stem(&self) -> &'static str {
+        match self {
+            Self::AcademicDegrees => "academic_degrees",
+            Self::AmbiguousExpansions => "ambiguous_expansions",
+            Self::BuildingTypes => "building_types",
+            Self::Chains => "chains",
+            Self::CompanyTypes => "company_types",
+            Self::ConcatenatedPrefixesSeparable => "concatenated_prefixes_separable",
+            Self::ConcatenatedSuffixesInseparable => "concatenated_suffixes_inseparable",
+            Self::ConcatenatedSuffixesSeparable => "concatenated_suffixes_separable",
+            Self::CrossStreets => "cross_streets",
+            Self::Directionals => "directionals",
+            Self::Elisions => "elisions",
+            Self::Entrances => "entrances",
+            Self::GivenNames => "given_names",
+            Self::HouseNumbers => "house_numbers",
+            Self::LevelTypesBasement => "level_types_basement",
+            Self::LevelTypesMezzanine => "level_types_mezzanine",
+            Self::LevelTypesNumbered => "level_types_numbered",
+            Self::LevelTypesStandalone => "level_types_standalone",
+            Self::LevelTypesSubBasement => "level_types_sub_basement",
+            Self::Near => "near",
+            Self::NoNumber => "no_number",
+            Self::Nulls => "nulls",
+            Self::Number => "number",
+            Self::People => "people",
+            Self::PersonalSuffixes => "personal_suffixes",
+            Self::PersonalTitles => "personal_titles",
+            Self::PlaceNames => "place_names",
+            Self::PostOffice => "post_office",
+            Self::Postcodes => "postcodes",
+            Self::Qualifiers => "qualifiers",
+            Self::Staircases => "staircases",
+            Self::Stopwords => "stopwords",
+            Self::StreetNames => "street_names",
+            Self::StreetTypes => "street_types",
+            Self::Surnames => "surnames",
+            Self::Synonyms => "synonyms",
+            Self::Toponyms => "toponyms",
+            Self::UnitDirections => "unit_directions",
+            Self::UnitTypesNumbered => "unit_types_numbered",
+            Self::UnitTypesStandalone => "unit_types_standalone",
+        }
+    }
+}
+
+/// Dictionaries applied to street names.
+pub const ROAD_CATEGORIES: &[DictionaryCategory] = &[
+    DictionaryCategory::StreetTypes,
+    DictionaryCategory::Directionals,
+    DictionaryCategory::StreetNames,
+];
+
+/// Dictionaries applied to units, e.g. "apt 4".
+pub const UNIT_CATEGORIES: &[DictionaryCategory] = &[
+    DictionaryCategory::UnitTypesNumbered,
+    DictionaryCategory::UnitTypesStandalone,
+    DictionaryCategory::UnitDirections,
+];
+
+/// Dictionaries applied to levels, e.g. "floor 2" or "basement".
+pub const LEVEL_CATEGORIES: &[DictionaryCategory] = &[
+    DictionaryCategory::LevelTypesNumbered,
+    DictionaryCategory::LevelTypesStandalone,
+    DictionaryCategory::LevelTypesBasement,
+    DictionaryCategory::LevelTypesSubBasement,
+    DictionaryCategory::LevelTypesMezzanine,
+];
+
+/// Dictionaries applied to the names of places.
+pub const NAME_CATEGORIES: &[DictionaryCategory] = &[
+    DictionaryCategory::PlaceNames,
+    DictionaryCategory::Synonyms,
+    DictionaryCategory::BuildingTypes,
+    DictionaryCategory::CompanyTypes,
+];
+
+/// Dictionaries applied to the names of administrative areas.
+pub const TOPONYM_CATEGORIES: &[DictionaryCategory] = &[DictionaryCategory::Toponyms];
+
+/// The directory name used for a language, which is its ISO 639-1 code.
+pub fn language_code(language: &Language) -> String {
+    language.iso_code_639_1().to_string()
+}
+
+/// The raw contents of a dictionary file, if one exists for the language and category.
+pub(crate) fn dictionary_file(language_code: &str, category: DictionaryCategory) -> Option<&str> {
+    DICTIONARY_FILES
+        .iter()
+        .find(|(language, stem, _)| *language == language_code && *stem == category.file_stem())
+        .map(|(_, _, contents)| *contents)
+}
+
+type RegistryKey = (String, Vec<DictionaryCategory>);
+
+lazy_static! {
+    static ref REGISTRY: RwLock<HashMap<RegistryKey, Arc<SubstitutionDict>>> =
+        RwLock::new(HashMap::new());
+    /// Number tables parsed so far, by language code, see [`numbers`].
+    static ref NUMBERS: RwLock<HashMap<String, Arc<SubstitutionDict>>> =
+        RwLock::new(HashMap::new());
+    /// Dictionaries loaded at runtime with [`load_dictionary_dir`], by language and category.
+    static ref CUSTOM_DICTIONARIES: RwLock<HashMap<(String, DictionaryCategory), SubstitutionDict>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Load extra dictionaries from a directory laid out like the bundled ones: one subdirectory
+/// per ISO 639-1 language code (or `all`), containing pipe-separated files named after a
+/// [`DictionaryCategory`], e.g. `en/street_types.txt`. Their substitutions are added to the
+/// bundled dictionaries of the same language and category, for both indexing and queries.
+/// Names and queries are substituted a word at a time, so a phrase of several words can only
+/// stand in for a single word, e.g. `zipzap burgers|zzb` expands "zzb" but doesn't match
+/// "zipzap burgers". Returns the number of files loaded.
+///
+/// # Panics
+///
+/// Panics if the registry lock is poisoned.
+pub fn load_dictionary_dir(path: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut loaded = 0;
+    let mut custom = CUSTOM_DICTIONARIES.write().unwrap();
+    for language_dir in fs::read_dir(path)? {
+        let language_dir = language_dir?.path();
+        if !language_dir.is_dir() {
+            continue;
+        }
+        let language = language_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        for file in fs::read_dir(&language_dir)? {
+            let file = file?.path();
+            if file.extension().is_none_or(|ext| ext != "txt") {
+                continue;
+            }
+            let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+            let Some(category) = DictionaryCategory::from_file_stem(&stem) else {
+                warn!("Skipping dictionary {:?} with unknown category", file);
+                continue;
+            };
+            let dict = SubstitutionDict::parse(&fs::read_to_string(&file)?)?;
+            custom
+                .entry((language.clone(), category))
+                .or_insert_with(SubstitutionDict::empty)
+                .extend(&dict);
+            loaded += 1;
+        }
+    }
+    drop(custom);
+
+    // Merged dictionaries built before this point don't include the new substitutions.
+    REGISTRY.write().unwrap().clear();
+    info!("Loaded {} custom dictionaries from {:?}", loaded, path);
+    Ok(loaded)
+}
+
+/// Look up the combined substitutions of several dictionary categories for a language,
+/// including the language-independent dictionaries for those categories and any loaded with
+/// [`load_dictionary_dir`]. Passing [`ANY_LANGUAGE`] merges the dictionaries of every known
+/// language. Dictionaries are parsed on first use and cached for the lifetime of the process.
+///
+/// # Panics
+///
+/// Panics if the registry lock is poisoned.
+pub fn dictionary(language_code: &str, categories: &[DictionaryCategory]) -> Arc<SubstitutionDict> {
+    let key = (language_code.to_string(), categories.to_vec());
+    if let Some(dict) = REGISTRY.read().unwrap().get(&key) {
+        return dict.clone();
+    }
+
+    let mut dict = SubstitutionDict::empty();
+    if language_code == ANY_LANGUAGE {
+        for language in Language::all().iter().sorted() {
+            dict.extend(&dictionary(&self::language_code(language), categories));
+        }
+    } else {
+        let custom = CUSTOM_DICTIONARIES.read().unwrap();
+        for category in categories {
+            for language in [language_code, ALL_LANGUAGES] {
+                if let Some(Ok(file_dict)) =
+                    dictionary_file(language, *category).map(SubstitutionDict::parse)
+                {
+                    dict.extend(&file_dict);
+                }
+                if let Some(custom_dict) = custom.get(&(language.to_string(), *category)) {
+                    dict.extend(custom_dict);
+                }
+            }
+        }
+    }
+
+    let dict = Arc::new(dict);
+    REGISTRY.write().unwrap().insert(key, dict.clone());
+    dict
+}
+
+/// Cardinal and ordinal numbers written as words for a language, e.g. "fifth|5th". The
+/// canonical form of each line is its numeric spelling. These aren't part of libpostal's
+/// dictionaries, so they live in `numbers/` rather than `dictionaries/`. Passing
+/// [`ANY_LANGUAGE`] merges the tables of every known language, which is only good for telling
+/// whether a phrase is a number at all: where languages disagree on what it means, the first
+/// language's meaning is kept.
+///
+/// # Panics
+///
+/// Panics if the registry lock is poisoned.
+pub fn numbers(language_code: &str) -> Arc<SubstitutionDict> {
+    if let Some(dict) = NUMBERS.read().unwrap().get(language_code) {
+        return dict.clone();
+    }
+
+    let mut dict = SubstitutionDict::empty();
+    if language_code == ANY_LANGUAGE {
+        for language in Language::all().iter().sorted() {
+            dict.extend(&numbers(&self::language_code(language)));
+        }
+    } else if let Some(Ok(file_dict)) = NUMBER_FILES
+        .iter()
+        .find(|(language, _)| *language == language_code)
+        .map(|(_, contents)| SubstitutionDict::parse(contents))
+    {
+        dict = file_dict;
+    }
+
+    let dict = Arc::new(dict);
+    NUMBERS
+        .write()
+        .unwrap()
+        .insert(language_code.to_string(), dict.clone());
+    dict
+}
+
+#[cfg(test)]
+mod test {
+    use lingua::Language;
+
+    use crate::{query::expand_token, substitutions::permute_name};
+
+    use super::load_dictionary_dir;
+
+    #[test]
+    fn test_load_dictionary_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("en")).unwrap();
+        std::fs::write(
+            dir.path().join("en").join("synonyms.txt"),
+            "zipzap burgers|zzb\n",
+        )
+        .unwrap();
+        // Populate the cache first to check that loading invalidates it.
+        assert!(!expand_token("zzb", &[]).contains(&"zipzap burgers".to_string()));
+
+        assert_eq!(load_dictionary_dir(dir.path()).unwrap(), 1);
+        assert!(expand_token("zzb", &[]).contains(&"zipzap burgers".to_string()));
+        assert!(permute_name("ZZB Downtown", &Language::English)
+            .unwrap()
+            .contains(&"zipzap burgers downtown".to_string()));
+    }
+}
@@ -0,0 +1,437 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use reqwest::{header::RANGE, StatusCode};
+use tantivy::{
+    directory::{
+        error::{DeleteError, LockError, OpenReadError, OpenWriteError},
+        DirectoryLock, FileHandle, Lock, WatchCallback, WatchHandle, WritePtr,
+    },
+    Directory,
+};
+use tantivy_uffd::RemoteDirectory;
+
+/// Remote files are fetched this many bytes at a time.
+pub const CHUNK_SIZE: usize = 2 * 1024 * 1024;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The module tantivy-uffd logs its chunk fetches from.
+const UFFD_TARGET: &str = "tantivy_uffd::uffd";
+
+static LENGTH_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static ATOMIC_READ_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static CHUNK_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static BYTES_FETCHED: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    /// The length of every remote file opened so far, by URL. tantivy-uffd keeps the same
+    /// process-wide cache, and only requests the length of files that aren't in it.
+    static ref FILE_LENGTHS: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+    /// tantivy-uffd's directory for each remote index opened so far, by base URL. Each one has
+    /// its own runtime, and each file it opens is mapped into memory with a thread handling its
+    /// page faults, none of which tantivy-uffd ever releases. Opening an index again, e.g. to
+    /// reload it, reuses them instead of making more.
+    static ref DIRECTORIES: Mutex<HashMap<String, RemoteDirectory<CHUNK_SIZE>>> =
+        Mutex::new(HashMap::new());
+}
+
+thread_local! {
+    static BLOCKING_HTTP_CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::new();
+}
+
+/// Counters for requests made to remote indices, across every remote index in the process.
+///
+/// Chunks of file contents are fetched by tantivy-uffd as their pages are first touched, out of
+/// reach of the `Directory` API, so they're counted from its log records by
+/// [`RemoteFetchLogger`]. Pages that are still in memory are read without any lookup at all, so
+/// cache hits only count lookups that reach the directory: page faults served from a chunk that
+/// was recently fetched or is already being fetched, and file opens and whole-file reads served
+/// from its caches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemoteStats {
+    /// Requests for the length of a file, made the first time each file is opened.
+    pub length_requests: u64,
+    /// Requests for small files like `meta.json`, fetched whole when an index is opened.
+    pub atomic_read_requests: u64,
+    /// Range requests for chunks of file contents, not counting retries.
+    pub chunk_requests: u64,
+    /// Bytes fetched by whole-file reads and range requests.
+    pub bytes_fetched: u64,
+    /// Lookups served without making a request.
+    pub cache_hits: u64,
+}
+
+impl RemoteStats {
+    pub fn requests(&self) -> u64 {
+        self.length_requests + self.atomic_read_requests + self.chunk_requests
+    }
+
+    /// How many lookups were served without making a request, from 0 to 1.
+    pub fn cache_hit_ratio(&self) -> f64 {
+        let lookups = self.requests() + self.cache_hits;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.cache_hits as f64 / lookups as f64
+    }
+}
+
+pub fn remote_stats() -> RemoteStats {
+    RemoteStats {
+        length_requests: LENGTH_REQUESTS.load(Ordering::Relaxed),
+        atomic_read_requests: ATOMIC_READ_REQUESTS.load(Ordering::Relaxed),
+        chunk_requests: CHUNK_REQUESTS.load(Ordering::Relaxed),
+        bytes_fetched: BYTES_FETCHED.load(Ordering::Relaxed),
+        cache_hits: CACHE_HITS.load(Ordering::Relaxed),
+    }
+}
+
+/// Count a chunk fetch or cache hit that tantivy-uffd logged.
+fn count_uffd_record(message: &str) {
+    if let Some((chunk_idx, url)) = message
+        .strip_prefix("Fetching chunk: ")
+        .and_then(|fetch| fetch.split_once(" from "))
+    {
+        CHUNK_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        let len = FILE_LENGTHS.lock().unwrap().get(url).copied();
+        if let (Ok(chunk_idx), Some(len)) = (chunk_idx.parse::<usize>(), len) {
+            // The last chunk of a file is short.
+            let bytes = len.saturating_sub(chunk_idx * CHUNK_SIZE).min(CHUNK_SIZE);
+            BYTES_FETCHED.fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+    } else if message.starts_with("Using cached chunk: ")
+        || message.starts_with("Already requested chunk: ")
+    {
+        CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A logger that counts the chunk fetches tantivy-uffd logs for [`remote_stats`], and passes
+/// every record on to the logger it wraps.
+pub struct RemoteFetchLogger<L> {
+    inner: L,
+}
+
+impl<L: Log + 'static> RemoteFetchLogger<L> {
+    /// Install `inner` as the logger, counting chunk fetches on the way. tantivy-uffd logs cache
+    /// hits at trace level, so every record reaches this logger, and `inner` decides which ones
+    /// to keep.
+    pub fn init(inner: L) -> Result<(), SetLoggerError> {
+        log::set_logger(Box::leak(Box::new(Self { inner })))?;
+        log::set_max_level(LevelFilter::Trace);
+        Ok(())
+    }
+}
+
+impl<L: Log> Log for RemoteFetchLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == UFFD_TARGET || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target() == UFFD_TARGET {
+            match record.args().as_str() {
+                Some(message) => count_uffd_record(message),
+                None => count_uffd_record(&record.args().to_string()),
+            }
+        }
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+fn read_error(
+    path: &Path,
+    error: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> OpenReadError {
+    OpenReadError::IoError {
+        io_error: Arc::new(std::io::Error::other(error)),
+        filepath: path.to_path_buf(),
+    }
+}
+
+/// Wraps tantivy-uffd's remote directory to count requests to it, see [`remote_stats`].
+///
+/// Small files like `meta.json` are fetched here rather than by tantivy-uffd, which can't tell a
+/// missing file from one that exists, and caches them forever. Each directory opened for an index
+/// reads its own `meta.json`, and shares the files it lists with the others opened for the same
+/// index. Files of versions that have since been replaced stay open until the process exits.
+#[derive(Debug, Clone)]
+pub struct InstrumentedDirectory {
+    base_url: String,
+    inner: RemoteDirectory<CHUNK_SIZE>,
+    atomic_reads: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+}
+
+impl InstrumentedDirectory {
+    /// Open the directory at `base_url`, with or without a trailing slash. tantivy-uffd retries
+    /// chunks that can't be fetched until they can be, so this fails if the server doesn't
+    /// answer range requests with part of a file rather than leave searches waiting forever.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock on the directories opened so far is poisoned.
+    pub fn open(base_url: &str) -> std::io::Result<Self> {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let meta_url = format!("{}/meta.json", base_url);
+        CHUNK_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        let response = BLOCKING_HTTP_CLIENT
+            .with(|client| {
+                client
+                    .get(&meta_url)
+                    .header(RANGE, "bytes=0-0")
+                    .timeout(REQUEST_TIMEOUT)
+                    .send()
+            })
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .map_err(std::io::Error::other)?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(std::io::Error::other(format!(
+                "{} doesn't support range requests, it answered one with {}",
+                base_url,
+                response.status()
+            )));
+        }
+        BYTES_FETCHED.fetch_add(
+            response.content_length().unwrap_or_default(),
+            Ordering::Relaxed,
+        );
+        let inner = DIRECTORIES
+            .lock()
+            .unwrap()
+            .entry(base_url.clone())
+            .or_insert_with(|| RemoteDirectory::new(&base_url))
+            .clone();
+        Ok(Self {
+            inner,
+            base_url,
+            atomic_reads: Arc::default(),
+        })
+    }
+
+    fn url(&self, path: &Path) -> String {
+        format!("{}/{}", self.base_url, path.display())
+    }
+}
+
+impl Directory for InstrumentedDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let url = self.url(path);
+        if FILE_LENGTHS.lock().unwrap().contains_key(&url) {
+            CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+        } else {
+            LENGTH_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        }
+        let handle = self.inner.get_file_handle(path)?;
+        FILE_LENGTHS.lock().unwrap().insert(url, handle.len());
+        Ok(handle)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.inner.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        let url = self.url(path);
+        if FILE_LENGTHS.lock().unwrap().contains_key(&url) {
+            CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+            return Ok(true);
+        }
+        LENGTH_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        let response = BLOCKING_HTTP_CLIENT
+            .with(|client| client.head(&url).timeout(REQUEST_TIMEOUT).send())
+            .map_err(|err| read_error(path, err))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        response
+            .error_for_status()
+            .map_err(|err| read_error(path, err))?;
+        Ok(true)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        self.inner.open_write(path)
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let mut atomic_reads = self.atomic_reads.lock().unwrap();
+        if let Some(bytes) = atomic_reads.get(path) {
+            CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+            return Ok(bytes.clone());
+        }
+        ATOMIC_READ_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        let url = self.url(path);
+        let response = BLOCKING_HTTP_CLIENT
+            .with(|client| client.get(&url).timeout(REQUEST_TIMEOUT).send())
+            .map_err(|err| read_error(path, err))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(OpenReadError::FileDoesNotExist(path.to_path_buf()));
+        }
+        let bytes = response
+            .error_for_status()
+            .and_then(reqwest::blocking::Response::bytes)
+            .map_err(|err| read_error(path, err))?
+            .to_vec();
+        BYTES_FETCHED.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        atomic_reads.insert(path.to_path_buf(), bytes.clone());
+        Ok(bytes)
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        self.inner.atomic_write(path, data)
+    }
+
+    fn sync_directory(&self) -> std::io::Result<()> {
+        self.inner.sync_directory()
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.inner.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        self.inner.watch(watch_callback)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::PathBuf,
+    };
+
+    use log::{Log, Metadata, Record};
+    use tantivy::{
+        collector::Count,
+        doc,
+        query::TermQuery,
+        schema::{IndexRecordOption, Schema, TEXT},
+        Index, Term,
+    };
+
+    use super::{remote_stats, InstrumentedDirectory, RemoteFetchLogger};
+
+    struct Discard;
+
+    impl Log for Discard {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            false
+        }
+
+        fn log(&self, _record: &Record) {}
+
+        fn flush(&self) {}
+    }
+
+    /// Serve the files in `dir` over HTTP, with just enough of the protocol for the directory.
+    /// Servers that don't support range requests send whole files instead.
+    fn serve(dir: PathBuf, ranges: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+                let request = lines.next().unwrap().unwrap();
+                let mut range = None;
+                for line in lines
+                    .map(Result::unwrap)
+                    .take_while(|line| !line.is_empty())
+                {
+                    if let Some(bytes) = line.to_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = bytes.split_once('-').unwrap();
+                        range = Some((start.parse().unwrap(), end.parse::<usize>().unwrap() + 1));
+                    }
+                }
+                let (method, path) = request.split_once(' ').unwrap();
+                let path = path.split(' ').next().unwrap().trim_start_matches('/');
+                let Ok(body) = std::fs::read(dir.join(path)) else {
+                    write!(
+                        stream,
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+                    )
+                    .unwrap();
+                    continue;
+                };
+                let (status, body) = match range.filter(|_| ranges) {
+                    Some((start, end)) => (
+                        "206 Partial Content",
+                        body[start..end.min(body.len())].to_vec(),
+                    ),
+                    None => ("200 OK", body),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                if method == "GET" {
+                    stream.write_all(&body).unwrap();
+                }
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_remote_stats() {
+        RemoteFetchLogger::init(Discard).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut schema = Schema::builder();
+        let name = schema.add_text_field("name", TEXT);
+        let index = Index::create_in_dir(dir.path(), schema.build()).unwrap();
+        let mut writer = index.writer(15_000_000).unwrap();
+        for i in 0..10_000 {
+            writer
+                .add_document(doc!(name => format!("poi {}", i)))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+
+        let directory =
+            InstrumentedDirectory::open(&serve(dir.path().to_path_buf(), true)).unwrap();
+        let index = Index::open(directory).unwrap();
+        let before = remote_stats();
+        let query = TermQuery::new(Term::from_field_text(name, "poi"), IndexRecordOption::Basic);
+        let search = || index.reader().unwrap().searcher().search(&query, &Count);
+        assert_eq!(search().unwrap(), 10_000);
+        let first = remote_stats();
+        assert!(first.length_requests > before.length_requests);
+        assert!(first.chunk_requests > before.chunk_requests);
+        assert!(first.bytes_fetched > before.bytes_fetched);
+
+        // Searching again touches pages that are already in memory, and files already opened.
+        assert_eq!(search().unwrap(), 10_000);
+        let second = remote_stats();
+        assert_eq!(second.requests(), first.requests());
+        assert!(second.cache_hits > first.cache_hits);
+    }
+
+    #[test]
+    fn test_requires_range_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("meta.json"), "{}").unwrap();
+        assert!(InstrumentedDirectory::open(&serve(dir.path().to_path_buf(), true)).is_ok());
+        assert!(InstrumentedDirectory::open(&serve(dir.path().to_path_buf(), false)).is_err());
+    }
+}
@@ -4,4 +4,14 @@ use thiserror::Error;
 pub enum AirmailError {
     #[error("unable to count")]
     UnableToCount,
+
+    #[error("invalid bbox: {0}")]
+    InvalidBbox(String),
+
+    #[error("{name} `{value}` is out of range, it must be between -{limit} and {limit}")]
+    CoordinateOutOfRange {
+        name: &'static str,
+        value: f64,
+        limit: f64,
+    },
 }
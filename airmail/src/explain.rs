@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use lingua::Language;
+use serde::Serialize;
+use tantivy::{
+    query::{EnableScoring, Explanation, Query, Scorer, Weight},
+    DocAddress, DocSet, Searcher, SegmentReader,
+};
+
+use crate::{parser::AddressComponent, query::NearQuery};
+
+/// One clause of a constructed query.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryClause {
+    /// The field matched against, e.g. "content" or "phonetic".
+    pub field: &'static str,
+    /// The query text the clause matches, or a phonetic code or tag.
+    pub text: String,
+    /// Abbreviations and expansions also accepted in place of `text`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<String>,
+    /// Whether a result has to match this clause.
+    pub required: bool,
+    pub boost: f32,
+    /// Edit distance tolerated for typos, for fuzzy clauses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_edit_distance: Option<u8>,
+}
+
+impl QueryClause {
+    pub(crate) fn new(field: &'static str, text: &str, required: bool, boost: f32) -> Self {
+        Self {
+            field,
+            text: text.to_string(),
+            alternatives: Vec::new(),
+            required,
+            boost,
+            max_edit_distance: None,
+        }
+    }
+}
+
+/// How a query was turned into clauses, along with a query for each individual term of each
+/// clause so that matches can be attributed to them.
+#[derive(Debug, Default)]
+pub struct QueryPlan {
+    pub components: Vec<AddressComponent>,
+    /// The languages the query might be written in, see [`crate::query::query_languages`].
+    pub languages: Vec<Language>,
+    pub clauses: Vec<QueryClause>,
+    terms: Vec<(String, Box<dyn Query>)>,
+}
+
+impl QueryPlan {
+    pub(crate) fn add_clause(&mut self, clause: QueryClause) {
+        self.clauses.push(clause);
+    }
+
+    /// Remember a query matching a single term, as "field:text".
+    pub(crate) fn add_term(&mut self, field: &str, text: &str, query: &dyn Query) {
+        self.terms
+            .push((format!("{}:{}", field, text), query.box_clone()));
+    }
+
+    /// The terms of the plan a document matches.
+    pub(crate) fn matched_terms(
+        &self,
+        searcher: &Searcher,
+        doc_address: DocAddress,
+    ) -> Vec<String> {
+        self.terms
+            .iter()
+            .filter(|(_, query)| matches(query.as_ref(), searcher, doc_address).unwrap_or(false))
+            .map(|(term, _)| term.clone())
+            .collect()
+    }
+
+    /// Break down a document's score for `query`, the query the plan was made for, into the
+    /// explanations of each of the plan's terms it matched.
+    ///
+    /// The whole query can't be explained by tantivy directly, see [`explain_match`].
+    pub(crate) fn explain(
+        &self,
+        query: &dyn Query,
+        searcher: &Searcher,
+        doc_address: DocAddress,
+    ) -> tantivy::Result<Explanation> {
+        let (weight, reader) = weight_for(query, searcher, doc_address)?;
+        let Some(mut scorer) = matching_scorer(weight.as_ref(), reader, doc_address.doc_id)? else {
+            return Err(tantivy::TantivyError::InvalidArgument(format!(
+                "document {:?} does not match the query",
+                doc_address
+            )));
+        };
+        let mut explanation = Explanation::new(
+            "score, from the matched terms before boosting ...",
+            scorer.score(),
+        );
+        for (term, query) in &self.terms {
+            if let Some(term_explanation) = explain_match(query.as_ref(), searcher, doc_address)? {
+                let mut detail =
+                    Explanation::new_with_string(term.clone(), term_explanation.value());
+                detail.add_detail(term_explanation);
+                explanation.add_detail(detail);
+            }
+        }
+        Ok(explanation)
+    }
+}
+
+fn weight_for<'a>(
+    query: &dyn Query,
+    searcher: &'a Searcher,
+    doc_address: DocAddress,
+) -> tantivy::Result<(Box<dyn Weight>, &'a SegmentReader)> {
+    let weight = query.weight(EnableScoring::enabled_from_searcher(searcher))?;
+    Ok((weight, searcher.segment_reader(doc_address.segment_ord)))
+}
+
+/// A fresh scorer for `weight` positioned on `doc`, or `None` if `doc` doesn't match.
+///
+/// A new scorer starts on the first document it matches, and seeking backwards from there is
+/// invalid: debug builds of tantivy panic and release builds can land on the wrong document. So
+/// a document before the first match is known not to match without seeking.
+fn matching_scorer(
+    weight: &dyn Weight,
+    reader: &SegmentReader,
+    doc: u32,
+) -> tantivy::Result<Option<Box<dyn Scorer>>> {
+    let mut scorer = weight.scorer(reader, 1.0)?;
+    if scorer.doc() > doc || scorer.seek(doc) != doc {
+        return Ok(None);
+    }
+    Ok(Some(scorer))
+}
+
+/// Whether a document matches `query`.
+fn matches(
+    query: &dyn Query,
+    searcher: &Searcher,
+    doc_address: DocAddress,
+) -> tantivy::Result<bool> {
+    let (weight, reader) = weight_for(query, searcher, doc_address)?;
+    Ok(matching_scorer(weight.as_ref(), reader, doc_address.doc_id)?.is_some())
+}
+
+/// Explain a document's score for `query` with a fresh weight, or `None` if it doesn't match.
+///
+/// `Weight::explain` makes the same backwards seek [`matching_scorer`] avoids, so only documents
+/// known to match are explained. That makes queries on single terms or phrases safe, but not
+/// boolean queries over anything other than terms, which explain every clause whether it
+/// matched or not.
+fn explain_match(
+    query: &dyn Query,
+    searcher: &Searcher,
+    doc_address: DocAddress,
+) -> tantivy::Result<Option<Explanation>> {
+    let (weight, reader) = weight_for(query, searcher, doc_address)?;
+    if matching_scorer(weight.as_ref(), reader, doc_address.doc_id)?.is_none() {
+        return Ok(None);
+    }
+    weight.explain(reader, doc_address.doc_id).map(Some)
+}
+
+/// Why a result was returned.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultExplanation {
+    /// The final score, which is the text relevance score scaled by importance.
+    pub score: f32,
+    pub importance: f64,
+    pub matched_terms: Vec<String>,
+    /// The text relevance score broken down by the terms that matched, or `None` if the result
+    /// couldn't be explained.
+    pub explanation: Option<Explanation>,
+}
+
+/// Where the time went in a search.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SearchTimings {
+    #[serde(serialize_with = "as_millis")]
+    pub plan: Duration,
+    #[serde(serialize_with = "as_millis")]
+    pub search: Duration,
+    #[serde(serialize_with = "as_millis")]
+    pub fetch: Duration,
+    #[serde(serialize_with = "as_millis")]
+    pub explain: Duration,
+    #[serde(serialize_with = "as_millis")]
+    pub total: Duration,
+}
+
+fn as_millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// Everything known about how a search was performed, for debugging ranking.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchExplanation {
+    /// The text that was searched for, which is the subject of a "near" query.
+    pub query: String,
+    pub components: Vec<AddressComponent>,
+    pub clauses: Vec<QueryClause>,
+    /// One per result, in the same order.
+    pub results: Vec<ResultExplanation>,
+    pub timings: SearchTimings,
+    /// How a query like "cafe near pike place" was split.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub near: Option<NearQuery>,
+    /// The search for the anchor of a "near" query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<Box<SearchExplanation>>,
+}
@@ -1,11 +1,14 @@
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use geo::Rect;
 use itertools::Itertools;
+use lingua::Language;
 use log::{trace, warn};
 use s2::region::RegionCoverer;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use tantivy::schema::Value;
 use tantivy::{
@@ -19,16 +22,24 @@ use tantivy::{
         IndexRecordOption, NumericOptions, OwnedValue, Schema, TextFieldIndexing, TextOptions,
         STORED,
     },
-    Searcher, TantivyDocument, Term,
+    DocAddress, DocId, Score, Searcher, SegmentReader, TantivyDocument, Term,
 };
-use tantivy_uffd::RemoteDirectory;
 use tokio::task::spawn_blocking;
-use unicode_segmentation::UnicodeSegmentation;
 
 use crate::error::AirmailError;
 use crate::{
+    bbox::Bbox,
+    confidence::assess,
+    directory::InstrumentedDirectory,
+    explain::{QueryClause, QueryPlan, ResultExplanation, SearchExplanation, SearchTimings},
+    native::{has_unsegmented, native_tokens, NativeTokenizer, NATIVE_TOKENIZER},
+    parser::{parse_tokens, tokenize, AddressComponent, AddressLabel},
+    phonetic::{phonetic_codes, PhoneticTokenizer, PHONETIC_TOKENIZER},
     poi::{AirmailPoi, SchemafiedPoi},
-    query::all_subsequences,
+    query::{
+        expand_token, is_stopword, max_edit_distance, normalize_query_numbers, plan_query,
+        query_languages, split_near,
+    },
 };
 
 // Field name keys.
@@ -39,11 +50,70 @@ pub const FIELD_S2CELL: &str = "s2cell";
 pub const FIELD_S2CELL_PARENTS: &str = "s2cell_parents";
 pub const FIELD_CATEGORY_JSON: &str = "category";
 pub const FIELD_TAGS: &str = "tags";
+pub const FIELD_IMPORTANCE: &str = "importance";
+pub const FIELD_PHONETIC: &str = "phonetic";
+pub const FIELD_NATIVE: &str = "native";
+pub const FIELD_ADMIN: &str = "admin";
+pub const FIELD_ADMIN_NAMES: &str = "admin_names";
+
+/// How much a POI's importance can boost its score. A POI with importance 1.0 scores
+/// `1.0 + IMPORTANCE_WEIGHT` times higher than an otherwise identical POI with importance 0.0.
+const IMPORTANCE_WEIGHT: f32 = 1.0;
+
+/// Boost for names that sound like a query token. This is low because phonetic codes are lossy,
+/// so they should only break ties between otherwise similar matches.
+const PHONETIC_BOOST: f32 = 0.3;
+
+/// Boost for names matching a query in its original script. Transliteration is lossy, so a
+/// native match is stronger evidence than a transliterated one.
+const NATIVE_BOOST: f32 = 2.0;
+
+/// Boost for admin areas matching a part of the query parsed as a locality or region, so a POI
+/// in Portland ranks above one on Portland Ave when searching "cafe, portland".
+const ADMIN_BOOST: f32 = 1.5;
+
+/// How far from the anchor of a query like "cafe near pike place" to look for results.
+const NEAR_RADIUS_METERS: f64 = 1500.0;
+/// How confident the best result for the whole of a query like "pizza around the corner" has to
+/// be for it to be taken as written rather than split around "around".
+const UNSPLIT_NEAR_CONFIDENCE: f64 = 0.9;
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// How long a request for a remote index's `meta.json` can take, so that a server that stops
+/// responding fails reloads and readiness checks instead of holding them up.
+const META_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Version of the index schema, recorded in the index when it's built. Bump this whenever
+/// fields are added or the way they're indexed changes.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Recorded in the index's metadata on each commit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexPayload {
+    /// Seconds since the Unix epoch.
+    built_at: u64,
+    schema_version: u32,
+}
+
+/// What's in an index and where it came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStatus {
+    pub num_docs: u64,
+    pub num_segments: usize,
+    /// When the index was last committed to, in seconds since the Unix epoch. Unknown for
+    /// indices built before this was recorded.
+    pub built_at: Option<u64>,
+    /// The [`SCHEMA_VERSION`] the index was built with, unknown for indices built before
+    /// versioning.
+    pub schema_version: Option<u32>,
+    pub is_remote: bool,
+}
 
 #[derive(Clone)]
 pub struct AirmailIndex {
     tantivy_index: Arc<tantivy::Index>,
     is_remote: bool,
+    remote_url: Option<String>,
 }
 
 impl AirmailIndex {
@@ -65,6 +135,19 @@ impl AirmailIndex {
             .set_indexed()
             .set_stored()
             .set_fast();
+        let phonetic_options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_fieldnorms(false)
+                .set_tokenizer(PHONETIC_TOKENIZER)
+                .set_index_option(IndexRecordOption::Basic),
+        );
+        let native_options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_fieldnorms(false)
+                .set_tokenizer(NATIVE_TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        let importance_options = NumericOptions::default().set_fast();
         assert!(!s2cell_parent_index_options.fieldnorms());
         assert!(!s2cell_index_options.fieldnorms());
 
@@ -75,9 +158,46 @@ impl AirmailIndex {
         let _ = schema_builder.add_u64_field(FIELD_S2CELL_PARENTS, s2cell_parent_index_options);
         let _ = schema_builder.add_json_field(FIELD_TAGS, STORED);
         let _ = schema_builder.add_text_field(FIELD_CATEGORY_JSON, STORED);
+        let _ = schema_builder.add_f64_field(FIELD_IMPORTANCE, importance_options);
+        let _ = schema_builder.add_text_field(FIELD_PHONETIC, phonetic_options);
+        let _ = schema_builder.add_text_field(FIELD_NATIVE, native_options);
+        let _ = schema_builder.add_text_field(FIELD_ADMIN, text_options.clone());
+        let _ = schema_builder.add_text_field(FIELD_ADMIN_NAMES, STORED);
         schema_builder.build()
     }
 
+    fn register_tokenizers(tantivy_index: &tantivy::Index) {
+        tantivy_index
+            .tokenizers()
+            .register(PHONETIC_TOKENIZER, PhoneticTokenizer);
+        tantivy_index
+            .tokenizers()
+            .register(NATIVE_TOKENIZER, NativeTokenizer);
+    }
+
+    /// Indices built before the address parser was introduced don't have this field.
+    fn field_admin(&self) -> Option<tantivy::schema::Field> {
+        self.tantivy_index.schema().get_field(FIELD_ADMIN).ok()
+    }
+
+    /// Indices built before admin names were stored don't have this field.
+    fn field_admin_names(&self) -> Option<tantivy::schema::Field> {
+        self.tantivy_index
+            .schema()
+            .get_field(FIELD_ADMIN_NAMES)
+            .ok()
+    }
+
+    /// Indices built before native-script names were introduced don't have this field.
+    fn field_native(&self) -> Option<tantivy::schema::Field> {
+        self.tantivy_index.schema().get_field(FIELD_NATIVE).ok()
+    }
+
+    /// Indices built before phonetic matching was introduced don't have this field.
+    fn field_phonetic(&self) -> Option<tantivy::schema::Field> {
+        self.tantivy_index.schema().get_field(FIELD_PHONETIC).ok()
+    }
+
     fn field_content(&self) -> tantivy::schema::Field {
         self.tantivy_index
             .schema()
@@ -121,26 +241,31 @@ impl AirmailIndex {
         let schema = Self::schema();
         let tantivy_index =
             tantivy::Index::open_or_create(MmapDirectory::open(index_dir)?, schema)?;
+        Self::register_tokenizers(&tantivy_index);
         Ok(Self {
             tantivy_index: Arc::new(tantivy_index),
             is_remote: false,
+            remote_url: None,
         })
     }
 
     pub fn new(index_dir: &str) -> Result<Self> {
         let tantivy_index = tantivy::Index::open_in_dir(index_dir)?;
+        Self::register_tokenizers(&tantivy_index);
         Ok(Self {
             tantivy_index: Arc::new(tantivy_index),
             is_remote: false,
+            remote_url: None,
         })
     }
 
     pub fn new_remote(base_url: &str) -> Result<Self> {
-        let tantivy_index =
-            tantivy::Index::open(RemoteDirectory::<{ 2 * 1024 * 1024 }>::new(base_url))?;
+        let tantivy_index = tantivy::Index::open(InstrumentedDirectory::open(base_url)?)?;
+        Self::register_tokenizers(&tantivy_index);
         Ok(Self {
             tantivy_index: Arc::new(tantivy_index),
             is_remote: true,
+            remote_url: Some(base_url.to_string()),
         })
     }
 
@@ -176,24 +301,259 @@ impl AirmailIndex {
         Ok(count.await?.ok_or(AirmailError::UnableToCount)?)
     }
 
+    pub async fn status(&self) -> Result<IndexStatus> {
+        let index = self.tantivy_index.clone();
+        let is_remote = self.is_remote;
+        spawn_blocking(move || {
+            let searcher = index.reader()?.searcher();
+            let payload = index
+                .load_metas()?
+                .payload
+                .and_then(|payload| serde_json::from_str::<IndexPayload>(&payload).ok());
+            Ok(IndexStatus {
+                num_docs: searcher.num_docs(),
+                num_segments: searcher.segment_readers().len(),
+                built_at: payload.as_ref().map(|payload| payload.built_at),
+                schema_version: payload.map(|payload| payload.schema_version),
+                is_remote,
+            })
+        })
+        .await?
+    }
+
+    /// Open a remote index if `location` is an HTTP(S) URL, otherwise a local index directory.
+    pub fn open(location: &str) -> Result<Self> {
+        if location.starts_with("http") {
+            Self::new_remote(location)
+        } else {
+            Self::new(location)
+        }
+    }
+
+    /// Read the `meta.json` of the index at `location`, bypassing any cache. Its contents change
+    /// whenever a new version of the index is published there.
+    pub async fn read_meta(location: &str) -> Result<String> {
+        if location.starts_with("http") {
+            let meta_url = format!("{}/meta.json", location.trim_end_matches('/'));
+            Ok(reqwest::Client::new()
+                .get(&meta_url)
+                .timeout(META_TIMEOUT)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?)
+        } else {
+            Ok(tokio::fs::read_to_string(Path::new(location).join("meta.json")).await?)
+        }
+    }
+
+    /// Check that the index can serve searches by running `canary_query`. For remote indices,
+    /// this also checks that the server is still reachable, since the directory caches the
+    /// index metadata and would otherwise only find out on a cache miss.
+    pub async fn check_ready(&self, canary_query: &str) -> Result<()> {
+        if let Some(remote_url) = &self.remote_url {
+            let meta_url = format!("{}/meta.json", remote_url.trim_end_matches('/'));
+            reqwest::Client::new()
+                .head(&meta_url)
+                .timeout(META_TIMEOUT)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        self.search(canary_query, false, None, None, &[]).await?;
+        Ok(())
+    }
+
+    /// A query matching some text in the content field, as a phrase if it's multiple words.
+    fn text_query(&self, text: &str) -> Box<dyn Query> {
+        let terms = text
+            .split_whitespace()
+            .map(|word| Term::from_field_text(self.field_content(), word))
+            .collect_vec();
+        if terms.len() > 1 {
+            Box::new(PhraseQuery::new(terms))
+        } else {
+            Box::new(TermQuery::new(
+                Term::from_field_text(self.field_content(), text),
+                IndexRecordOption::Basic,
+            ))
+        }
+    }
+
+    /// Whether some POI in the index is in an admin area with this (sanitized) name. This is
+    /// always false for remote indices, where each lookup would be a round trip.
+    fn is_admin(&self, searcher: &Searcher, phrase: &str) -> bool {
+        let Some(field) = self.field_admin() else {
+            return false;
+        };
+        if self.is_remote {
+            return false;
+        }
+        let terms = phrase
+            .split_whitespace()
+            .map(|word| Term::from_field_text(field, word))
+            .collect_vec();
+        match terms.len() {
+            0 => false,
+            1 => searcher.doc_freq(&terms[0]).is_ok_and(|freq| freq > 0),
+            _ => searcher
+                .search(&PhraseQuery::new(terms), &Count)
+                .is_ok_and(|count| count > 0),
+        }
+    }
+
+    /// Parse a query into address components, recognizing localities by the admin areas in
+    /// this index. See [`crate::parser::parse`].
+    pub async fn parse(&self, query: &str) -> Result<Vec<AddressComponent>> {
+        let index = self.clone();
+        let tokens = tokenize(query);
+        spawn_blocking(move || {
+            let searcher = index.tantivy_index.reader()?.searcher();
+            Ok(parse_tokens(&tokens, |phrase| {
+                index.is_admin(&searcher, phrase)
+            }))
+        })
+        .await?
+    }
+
+    /// A query for admin areas named like a locality or region component of the query.
+    fn admin_query(&self, component: &AddressComponent) -> Option<Box<dyn Query>> {
+        let field = self.field_admin()?;
+        if !matches!(
+            component.label,
+            AddressLabel::Locality | AddressLabel::Region
+        ) {
+            return None;
+        }
+        let terms = component
+            .tokens
+            .iter()
+            .map(|token| Term::from_field_text(field, token))
+            .collect_vec();
+        let query: Box<dyn Query> = if terms.len() > 1 {
+            Box::new(PhraseQuery::new(terms))
+        } else {
+            Box::new(TermQuery::new(terms[0].clone(), IndexRecordOption::Basic))
+        };
+        Some(Box::new(BoostQuery::new(query, ADMIN_BOOST)))
+    }
+
+    /// A low-boost query for names that sound like a single query token, if lenient.
+    fn phonetic_query(
+        &self,
+        subsequence: &[String],
+        lenient: bool,
+        languages: &[Language],
+    ) -> Option<Box<dyn Query>> {
+        let field = self.field_phonetic()?;
+        if !lenient
+            || self.is_remote
+            || subsequence.len() != 1
+            || is_stopword(&subsequence[0], languages)
+        {
+            return None;
+        }
+        let codes = phonetic_codes(&subsequence[0]);
+        if codes.is_empty() {
+            return None;
+        }
+        let query = BooleanQuery::union(
+            codes
+                .iter()
+                .map(|code| -> Box<dyn Query> {
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(field, code),
+                        IndexRecordOption::Basic,
+                    ))
+                })
+                .collect(),
+        );
+        Some(Box::new(BoostQuery::new(Box::new(query), PHONETIC_BOOST)))
+    }
+
+    /// A query for names written the same way as the query, in its original script. This is
+    /// only useful when the query isn't plain ASCII, since otherwise it's no different from the
+    /// transliterated query.
+    fn native_query(&self, query: &str) -> Option<Box<dyn Query>> {
+        let field = self.field_native()?;
+        if query.is_ascii() {
+            return None;
+        }
+        let terms = native_tokens(query)
+            .iter()
+            .map(|token| Term::from_field_text(field, token))
+            .collect_vec();
+        let query: Box<dyn Query> = match terms.len() {
+            0 => return None,
+            1 => Box::new(TermQuery::new(terms[0].clone(), IndexRecordOption::Basic)),
+            _ => Box::new(PhraseQuery::new(terms)),
+        };
+        Some(Box::new(BoostQuery::new(query, NATIVE_BOOST)))
+    }
+
     async fn construct_query(
         &self,
         searcher: &Searcher,
         query: &str,
         tags: Option<Vec<String>>,
-        bbox: Option<Rect<f64>>,
+        bbox: Option<Bbox>,
         _boost_regions: &[(f32, Rect<f64>)],
         lenient: bool,
-    ) -> Box<dyn Query> {
+    ) -> (Box<dyn Query>, QueryPlan) {
+        let mut plan = QueryPlan::default();
         let mut queries: Vec<Box<dyn Query>> = Vec::new();
         let mut mandatory_queries: Vec<Box<dyn Query>> = Vec::new();
 
-        let tokens: Vec<String> = query
-            .split_word_bounds()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
+        // Transliterations of scripts written without spaces don't reliably split into the same
+        // words for a query and a name containing it, so a native match is enough on its own.
+        let mut native_alternative = None;
+        if let Some(native_query) = self.native_query(query) {
+            plan.add_term(FIELD_NATIVE, query, native_query.as_ref());
+            plan.add_clause(QueryClause::new(FIELD_NATIVE, query, false, NATIVE_BOOST));
+            if has_unsegmented(query) {
+                native_alternative = Some(native_query);
+            } else {
+                queries.push(native_query);
+            }
+        }
+
+        // Everything else matches against transliterated text, clause by address component.
+        let tokens = tokenize(query);
+        let languages = query_languages(query);
+        let mut components = parse_tokens(&tokens, |phrase| self.is_admin(searcher, phrase));
+        for component in &mut components {
+            component.tokens = normalize_query_numbers(&component.tokens, &languages);
+        }
+        // Units, regions, postcodes and countries are often missing from the indexed data, so
+        // they only help ranking.
+        let unindexed: Vec<&String> = components
+            .iter()
+            .filter(|component| {
+                matches!(
+                    component.label,
+                    AddressLabel::Unit
+                        | AddressLabel::Region
+                        | AddressLabel::Postcode
+                        | AddressLabel::Country
+                )
+            })
+            .flat_map(|component| &component.tokens)
             .collect();
-        for subsequence in all_subsequences(&tokens) {
+        for component in &components {
+            if let Some(admin_query) = self.admin_query(component) {
+                let admin = component.value();
+                plan.add_term(FIELD_ADMIN, &admin, admin_query.as_ref());
+                plan.add_clause(QueryClause::new(FIELD_ADMIN, &admin, false, ADMIN_BOOST));
+                queries.push(admin_query);
+            }
+        }
+        // Stopwords are optional unless there's nothing else to go on.
+        let only_stopwords = tokens
+            .iter()
+            .filter(|token| !token.chars().all(|c| c.is_ascii_punctuation()))
+            .all(|token| is_stopword(token, &languages));
+        for subsequence in plan_query(&components) {
             let possible_query = subsequence.join(" ");
             if possible_query
                 .chars()
@@ -213,6 +573,19 @@ impl AirmailIndex {
             if total_chars - non_alphabetic < 3 && non_alphabetic > 0 {
                 boost *= 3.0;
             }
+            let optional = subsequence.len() == 1
+                && ((!only_stopwords && is_stopword(&possible_query, &languages))
+                    || unindexed.contains(&&possible_query));
+            if let Some(phonetic_query) = self.phonetic_query(&subsequence, lenient, &languages) {
+                plan.add_term(FIELD_PHONETIC, &possible_query, phonetic_query.as_ref());
+                plan.add_clause(QueryClause::new(
+                    FIELD_PHONETIC,
+                    &possible_query,
+                    false,
+                    PHONETIC_BOOST,
+                ));
+                queries.push(phonetic_query);
+            }
             if subsequence.len() > 1 {
                 if self.is_remote {
                     let searcher = searcher.clone();
@@ -231,48 +604,55 @@ impl AirmailIndex {
                     });
                 }
 
-                if self.is_remote {
-                    queries.push(Box::new(BoostQuery::new(
-                        Box::new(PhraseQuery::new(
-                            subsequence
-                                .iter()
-                                .map(|s| Term::from_field_text(self.field_content(), s))
-                                .collect(),
-                        )),
-                        boost,
-                    )));
+                let terms = subsequence
+                    .iter()
+                    .map(|s| Term::from_field_text(self.field_content(), s))
+                    .collect();
+                let phrase_query: Box<dyn Query> = if self.is_remote {
+                    Box::new(PhraseQuery::new(terms))
                 } else {
-                    queries.push(Box::new(BoostQuery::new(
-                        Box::new(PhrasePrefixQuery::new(
-                            subsequence
-                                .iter()
-                                .map(|s| Term::from_field_text(self.field_content(), s))
-                                .collect(),
-                        )),
-                        boost,
-                    )));
-                }
-            } else if possible_query.len() >= 8 && lenient {
-                let query = if tokens.ends_with(&[possible_query]) {
-                    FuzzyTermQuery::new_prefix(term, 1, true)
+                    Box::new(PhrasePrefixQuery::new(terms))
+                };
+                plan.add_term(FIELD_CONTENT, &possible_query, phrase_query.as_ref());
+                plan.add_clause(QueryClause::new(
+                    FIELD_CONTENT,
+                    &possible_query,
+                    false,
+                    boost,
+                ));
+                queries.push(Box::new(BoostQuery::new(phrase_query, boost)));
+            } else {
+                let is_last_token = tokens.ends_with(std::slice::from_ref(&possible_query));
+                let distance = if lenient {
+                    max_edit_distance(&possible_query, self.is_remote)
                 } else {
-                    FuzzyTermQuery::new(term, 1, true)
+                    0
                 };
-                if self.is_remote {
-                    let searcher = searcher.clone();
-                    let query = query.clone();
-                    spawn_blocking(move || {
-                        let _ = searcher.search(&query, &Count);
-                    });
+                let mut query: Box<dyn Query> = if distance > 0 && is_last_token {
+                    Box::new(FuzzyTermQuery::new_prefix(term, distance, true))
+                } else if distance > 0 {
+                    Box::new(FuzzyTermQuery::new(term, distance, true))
+                } else if self.is_remote || !lenient || !is_last_token {
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+                } else {
+                    Box::new(FuzzyTermQuery::new_prefix(term, 0, false))
+                };
+                plan.add_term(FIELD_CONTENT, &possible_query, query.as_ref());
+                let alternatives = expand_token(&possible_query, &languages);
+                if !alternatives.is_empty() {
+                    let mut alternative_queries = vec![query];
+                    for alternative in &alternatives {
+                        let alternative_query = self.text_query(alternative);
+                        plan.add_term(FIELD_CONTENT, alternative, alternative_query.as_ref());
+                        alternative_queries.push(alternative_query);
+                    }
+                    query = Box::new(BooleanQuery::union(alternative_queries));
                 }
-                mandatory_queries.push(Box::new(BoostQuery::new(Box::new(query), boost)));
-            } else {
-                let query: Box<dyn Query> =
-                    if self.is_remote || !lenient || !tokens.ends_with(&[possible_query]) {
-                        Box::new(TermQuery::new(term, IndexRecordOption::Basic))
-                    } else {
-                        Box::new(FuzzyTermQuery::new_prefix(term, 0, false))
-                    };
+                plan.add_clause(QueryClause {
+                    alternatives,
+                    max_edit_distance: (distance > 0).then_some(distance),
+                    ..QueryClause::new(FIELD_CONTENT, &possible_query, !optional, boost)
+                });
                 if self.is_remote {
                     let searcher = searcher.clone();
                     let query = query.box_clone();
@@ -280,7 +660,12 @@ impl AirmailIndex {
                         let _ = searcher.search(&query, &Count);
                     });
                 }
-                mandatory_queries.push(Box::new(BoostQuery::new(query, boost)));
+                let query = Box::new(BoostQuery::new(query, boost));
+                if optional {
+                    queries.push(query);
+                } else {
+                    mandatory_queries.push(query);
+                }
             }
         }
 
@@ -289,35 +674,48 @@ impl AirmailIndex {
                 let term = Term::from_field_text(self.field_indexed_tag(), tag);
                 let query: Box<dyn Query> =
                     Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                plan.add_term(FIELD_INDEXED_TAG, tag, query.as_ref());
+                plan.add_clause(QueryClause::new(FIELD_INDEXED_TAG, tag, true, 1.0));
                 mandatory_queries.push(query);
             }
         }
 
+        plan.components = components;
+        plan.languages = languages;
+
         let optional = BooleanQuery::union(queries);
-        let required = BooleanQuery::intersection(mandatory_queries);
+        let mut required: Box<dyn Query> = Box::new(BooleanQuery::intersection(mandatory_queries));
+        if let Some(native_query) = native_alternative {
+            required = Box::new(BooleanQuery::union(vec![native_query, required]));
+        }
         let final_query = BooleanQuery::new(vec![
             (Occur::Should, Box::new(optional)),
-            (Occur::Must, Box::new(required)),
+            (Occur::Must, required),
         ]);
 
         if let Some(bbox) = bbox {
-            let region = s2::rect::Rect::from_degrees(
-                bbox.min().y,
-                bbox.min().x,
-                bbox.max().y,
-                bbox.max().x,
-            );
-            let covering_cells = {
-                let coverer = RegionCoverer {
-                    min_level: 0,
-                    max_level: 16,
-                    level_mod: 1,
-                    max_cells: 64,
-                };
-                let mut cellunion = coverer.covering(&region);
-                cellunion.normalize();
-                cellunion.0.iter().map(|c| c.0).collect_vec()
-            };
+            // A bbox crossing the antimeridian is covered a side at a time.
+            let covering_cells = bbox
+                .rects()
+                .iter()
+                .flat_map(|rect| {
+                    let region = s2::rect::Rect::from_degrees(
+                        rect.min().y,
+                        rect.min().x,
+                        rect.max().y,
+                        rect.max().x,
+                    );
+                    let coverer = RegionCoverer {
+                        min_level: 0,
+                        max_level: 16,
+                        level_mod: 1,
+                        max_cells: 64,
+                    };
+                    let mut cellunion = coverer.covering(&region);
+                    cellunion.normalize();
+                    cellunion.0.into_iter().map(|c| c.0)
+                })
+                .collect_vec();
             let covering_disjunction_clauses = covering_cells
                 .iter()
                 .map(|c| {
@@ -328,13 +726,12 @@ impl AirmailIndex {
                 })
                 .collect_vec();
             let covering_query = BooleanQuery::union(covering_disjunction_clauses);
-            return Box::new(BooleanQuery::intersection(vec![
-                Box::new(covering_query),
-                Box::new(final_query),
-            ]));
+            let query =
+                BooleanQuery::intersection(vec![Box::new(covering_query), Box::new(final_query)]);
+            return (Box::new(query), plan);
         }
 
-        Box::new(final_query)
+        (Box::new(final_query), plan)
     }
 
     /// This is public because I don't want one big mega-crate but its API should not be considered even remotely stable.
@@ -343,16 +740,97 @@ impl AirmailIndex {
         query: &str,
         request_leniency: bool,
         tags: Option<Vec<String>>,
-        bbox: Option<Rect<f64>>,
+        bbox: Option<Bbox>,
         boost_regions: &[(f32, Rect<f64>)],
     ) -> Result<Vec<(AirmailPoi, f32)>> {
+        let (results, _) = self
+            .search_explained(query, request_leniency, tags, bbox, boost_regions, false)
+            .await?;
+        Ok(results)
+    }
+
+    /// Search like [`Self::search`], also returning how the query was planned and how long each
+    /// step took. If `explain_results` is set, each result also gets the terms it matched and
+    /// tantivy's breakdown of its score, which costs a query per term per result.
+    pub async fn search_explained(
+        &self,
+        query: &str,
+        request_leniency: bool,
+        tags: Option<Vec<String>>,
+        bbox: Option<Bbox>,
+        boost_regions: &[(f32, Rect<f64>)],
+        explain_results: bool,
+    ) -> Result<(Vec<(AirmailPoi, f32)>, SearchExplanation)> {
+        let unsplit = self.search_text(
+            query,
+            request_leniency,
+            tags.clone(),
+            bbox,
+            boost_regions,
+            explain_results,
+        );
+        let Some(near) = split_near(query, &query_languages(query)) else {
+            return unsplit.await;
+        };
+
+        // For "cafe near pike place", find pike place first and then look for cafes around it.
+        // Near phrases can be ordinary words too, as in "pizza around the corner", so the split
+        // is only used if the query doesn't already match something well as written, and
+        // either half coming up empty means the query is treated as plain text instead.
+        let (results, explanation) = unsplit.await?;
+        if results
+            .first()
+            .is_some_and(|(poi, _)| poi.confidence >= UNSPLIT_NEAR_CONFIDENCE)
+        {
+            return Ok((results, explanation));
+        }
+        let (anchor, anchor_explanation) = self
+            .search_text(
+                &near.anchor,
+                request_leniency,
+                None,
+                bbox,
+                boost_regions,
+                explain_results,
+            )
+            .await?;
+        if let Some((anchor, _)) = anchor.first() {
+            let (near_results, mut near_explanation) = self
+                .search_text(
+                    &near.subject,
+                    request_leniency,
+                    tags,
+                    Some(Bbox::from(near_bbox(anchor.lat, anchor.lng))),
+                    boost_regions,
+                    explain_results,
+                )
+                .await?;
+            if !near_results.is_empty() {
+                near_explanation.near = Some(near);
+                near_explanation.anchor = Some(Box::new(anchor_explanation));
+                return Ok((near_results, near_explanation));
+            }
+        }
+        Ok((results, explanation))
+    }
+
+    async fn search_text(
+        &self,
+        query: &str,
+        request_leniency: bool,
+        tags: Option<Vec<String>>,
+        bbox: Option<Bbox>,
+        boost_regions: &[(f32, Rect<f64>)],
+        explain_results: bool,
+    ) -> Result<(Vec<(AirmailPoi, f32)>, SearchExplanation)> {
         let tantivy_reader = self.tantivy_index.reader()?;
         let searcher = tantivy_reader.searcher();
         let query_string = query.trim().replace("'s", "s");
 
         let start = std::time::Instant::now();
+        let mut timings = SearchTimings::default();
 
-        let query = self
+        let (query, plan) = self
             .construct_query(
                 &searcher,
                 &query_string,
@@ -362,38 +840,79 @@ impl AirmailIndex {
                 request_leniency,
             )
             .await;
+        timings.plan = start.elapsed();
 
         #[cfg(feature = "invasive_logging")]
         trace!("Search query: {:?}", &query);
 
         // Perform the search and then resolve the returned documents
-        let top_docs: Result<Vec<(f32, TantivyDocument)>> = spawn_blocking(move || {
-            let doc_addresses = searcher.search(&query, &TopDocs::with_limit(10))?;
+        type Docs = Vec<(f32, TantivyDocument, Option<ResultExplanation>)>;
+        let top_docs: Result<(Docs, QueryPlan, SearchTimings)> = spawn_blocking(move || {
+            // Indices built before importance was introduced don't have the field, so
+            // documents from them are ranked on text relevance alone.
+            let collector =
+                TopDocs::with_limit(10).tweak_score(|segment_reader: &SegmentReader| {
+                    let importance = segment_reader.fast_fields().f64(FIELD_IMPORTANCE).ok();
+                    move |doc: DocId, score: Score| {
+                        let importance = importance
+                            .as_ref()
+                            .and_then(|column| column.first(doc))
+                            .unwrap_or_default();
+                        score * (1.0 + IMPORTANCE_WEIGHT * importance as f32)
+                    }
+                });
+            let step = std::time::Instant::now();
+            let doc_addresses = searcher.search(&query, &collector)?;
+            timings.search = step.elapsed();
+
             let mut docs = vec![];
             for (score, doc_address) in doc_addresses {
-                if let Ok(doc) = searcher.doc::<TantivyDocument>(doc_address) {
-                    docs.push((score, doc));
-                }
+                let step = std::time::Instant::now();
+                let Ok(doc) = searcher.doc::<TantivyDocument>(doc_address) else {
+                    continue;
+                };
+                timings.fetch += step.elapsed();
+
+                let step = std::time::Instant::now();
+                let explanation = if explain_results {
+                    Some(ResultExplanation {
+                        score,
+                        importance: importance(&searcher, doc_address),
+                        matched_terms: plan.matched_terms(&searcher, doc_address),
+                        explanation: plan
+                            .explain(query.as_ref(), &searcher, doc_address)
+                            .map_err(|err| warn!("Failed to explain {:?}: {}", doc_address, err))
+                            .ok(),
+                    })
+                } else {
+                    None
+                };
+                timings.explain += step.elapsed();
+                docs.push((score, doc, explanation));
             }
 
-            Ok(docs)
+            Ok((docs, plan, timings))
         })
         .await?;
 
-        let top_docs = top_docs.map_err(|e| {
+        let (top_docs, plan, mut timings) = top_docs.map_err(|e| {
             warn!("Search failed: {:?}", e);
             e
         })?;
+        timings.total = start.elapsed();
 
         trace!(
             "Search took {:?} and yielded {} results",
-            start.elapsed(),
+            timings.total,
             top_docs.len()
         );
 
-        let results = top_docs
+        // Results are expected in the middle of the area searched, which for "near" queries is
+        // the anchor.
+        let focus = bbox.map(|bbox| bbox.center());
+        let (results, explanations): (Vec<_>, Vec<_>) = top_docs
             .into_iter()
-            .flat_map(|(score, doc)| {
+            .flat_map(|(score, doc, explanation)| {
                 let source = doc
                     .get_first(self.field_source())
                     .map(|value| value.as_str().unwrap_or_default().to_string())
@@ -407,16 +926,55 @@ impl AirmailIndex {
                     .map(|(k, v)| (k.to_string(), v.as_str().unwrap_or_default().to_string()))
                     .collect();
 
-                AirmailPoi::new(source, latlng.lat.deg(), latlng.lng.deg(), tags)
-                    .ok()
-                    .map(|poi| (poi, score))
+                let mut poi =
+                    AirmailPoi::new(source, latlng.lat.deg(), latlng.lng.deg(), tags).ok()?;
+                if let Some(field) = self.field_admin_names() {
+                    poi.admins = doc
+                        .get_all(field)
+                        .filter_map(|value| value.as_str())
+                        .map(str::to_string)
+                        .collect();
+                }
+                (poi.confidence, poi.match_type) =
+                    assess(&plan.components, &plan.languages, &poi, focus);
+                Some(((poi, score), explanation))
             })
-            .collect::<Vec<_>>();
+            .unzip();
 
-        Ok(results)
+        let explanation = SearchExplanation {
+            query: query_string,
+            components: plan.components,
+            clauses: plan.clauses,
+            results: explanations.into_iter().flatten().collect(),
+            timings,
+            near: None,
+            anchor: None,
+        };
+        Ok((results, explanation))
     }
 }
 
+/// The importance of a document, or zero for indices built before importance was introduced.
+fn importance(searcher: &Searcher, doc_address: DocAddress) -> f64 {
+    searcher
+        .segment_reader(doc_address.segment_ord)
+        .fast_fields()
+        .f64(FIELD_IMPORTANCE)
+        .ok()
+        .and_then(|column| column.first(doc_address.doc_id))
+        .unwrap_or_default()
+}
+
+/// The area searched around the anchor of a "near" query.
+fn near_bbox(lat: f64, lng: f64) -> Rect<f64> {
+    let lat_delta = NEAR_RADIUS_METERS / METERS_PER_DEGREE;
+    let lng_delta = lat_delta / lat.to_radians().cos().max(0.01);
+    Rect::new(
+        (lng - lng_delta, lat - lat_delta),
+        (lng + lng_delta, lat + lat_delta),
+    )
+}
+
 pub struct AirmailIndexWriter {
     tantivy_writer: tantivy::IndexWriter,
     schema: Schema,
@@ -459,6 +1017,29 @@ impl AirmailIndexWriter {
         );
 
         doc.add_u64(self.schema.get_field(FIELD_S2CELL)?, poi.s2cell);
+        if let Ok(field) = self.schema.get_field(FIELD_IMPORTANCE) {
+            doc.add_f64(field, poi.importance);
+        }
+        if let Ok(field) = self.schema.get_field(FIELD_NATIVE) {
+            for native in &poi.native {
+                doc.add_text(field, native);
+            }
+        }
+        if let Ok(field) = self.schema.get_field(FIELD_ADMIN) {
+            for admin in &poi.admins {
+                doc.add_text(field, admin);
+            }
+        }
+        if let Ok(field) = self.schema.get_field(FIELD_ADMIN_NAMES) {
+            for admin in &poi.admin_names {
+                doc.add_text(field, admin);
+            }
+        }
+        if let Ok(field) = self.schema.get_field(FIELD_PHONETIC) {
+            for name in &poi.names {
+                doc.add_text(field, name);
+            }
+        }
         for parent in poi.s2cell_parents {
             doc.add_u64(self.schema.get_field(FIELD_S2CELL_PARENTS)?, parent);
         }
@@ -468,7 +1049,197 @@ impl AirmailIndexWriter {
     }
 
     pub fn commit(mut self) -> Result<()> {
-        self.tantivy_writer.commit()?;
+        let payload = IndexPayload {
+            built_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+            schema_version: SCHEMA_VERSION,
+        };
+        let mut commit = self.tantivy_writer.prepare_commit()?;
+        commit.set_payload(&serde_json::to_string(&payload)?);
+        commit.commit()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use lingua::Language;
+
+    use super::AirmailIndex;
+    use crate::{
+        bbox::parse_bbox,
+        confidence::MatchType,
+        poi::{SchemafiedPoi, ToIndexPoi},
+    };
+
+    fn test_index(pois: Vec<ToIndexPoi>) -> (tempfile::TempDir, AirmailIndex) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = AirmailIndex::create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        for mut poi in pois {
+            poi.languages = vec![Language::English];
+            writer.add_poi(SchemafiedPoi::from(poi), "test").unwrap();
+        }
+        writer.commit().unwrap();
+        let index = AirmailIndex::new(dir.path().to_str().unwrap()).unwrap();
+        (dir, index)
+    }
+
+    fn named(name: &str, lat: f64, lng: f64) -> ToIndexPoi {
+        let tags = vec![("name".to_string(), name.to_string())];
+        ToIndexPoi::new(vec![name.to_string()], None, None, None, lat, lng, tags).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_explain_later_documents() {
+        // The cafe is after documents that don't match every term, which used to make explaining
+        // it seek scorers backwards.
+        let (_dir, index) = test_index(vec![
+            named("harbor view", 1.0, 1.0),
+            named("cafe paris", 2.0, 2.0),
+            named("harbor cafe", 3.0, 3.0),
+        ]);
+        let (results, explanation) = index
+            .search_explained("harbor cafe", false, None, None, &[], true)
+            .await
+            .unwrap();
+        assert!(!results.is_empty());
+        assert!(explanation
+            .results
+            .iter()
+            .all(|result| result.explanation.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_antimeridian_bbox() {
+        let (_dir, index) = test_index(vec![
+            named("harbor cafe", -18.1, 178.4),
+            named("harbor cafe", -18.1, -179.9),
+            named("harbor cafe", 47.6, -122.3),
+        ]);
+        let fiji = parse_bbox("177,-19,-178,-16").unwrap();
+        let results = index
+            .search("harbor cafe", false, None, Some(fiji), &[])
+            .await
+            .unwrap();
+        let mut lngs = results
+            .iter()
+            .map(|(poi, _)| poi.lng.round())
+            .collect::<Vec<_>>();
+        lngs.sort_by(f64::total_cmp);
+        assert_eq!(lngs, [-180.0, 178.0]);
+    }
+
+    #[tokio::test]
+    async fn test_confidence() {
+        let tags = vec![
+            ("addr:housenumber".to_string(), "123".to_string()),
+            (
+                "addr:street".to_string(),
+                "Fremont Avenue North".to_string(),
+            ),
+        ];
+        let mut address = ToIndexPoi::new(
+            vec![],
+            Some("123".to_string()),
+            Some("Fremont Avenue North".to_string()),
+            None,
+            47.65,
+            -122.35,
+            tags,
+        )
+        .unwrap();
+        // The city is only known from the admin areas the address is in, not its tags.
+        address.admins = vec!["Seattle".to_string()];
+        let (_dir, index) = test_index(vec![address, named("Fremont Cafe", 47.65, -122.35)]);
+
+        let results = index
+            .search("123 fremont ave n seattle", false, None, None, &[])
+            .await
+            .unwrap();
+        let (exact, _) = &results[0];
+        // Seattle only counts towards confidence because it's one of the result's admin areas.
+        assert_eq!(exact.admins, vec!["Seattle".to_string()]);
+        assert_eq!(exact.match_type, MatchType::Exact);
+        assert!(exact.confidence > 0.95, "{}", exact.confidence);
+
+        let results = index
+            .search("fremont ave n seattle", false, None, None, &[])
+            .await
+            .unwrap();
+        let (street, _) = &results[0];
+        assert_eq!(street.match_type, MatchType::Street);
+    }
+
+    #[tokio::test]
+    async fn test_near_query() {
+        let mut pub_ = named("The Corner", 51.5, -0.1);
+        pub_.importance = 0.5;
+        let (_dir, index) = test_index(vec![
+            pub_,
+            named("Pizza Express", 51.501, -0.1),
+            named("Pizza Around The Corner", 40.7, -74.0),
+        ]);
+        let name = |results: &[(crate::poi::AirmailPoi, f32)]| {
+            results[0]
+                .0
+                .tags
+                .iter()
+                .find(|(key, _)| key == "name")
+                .map(|(_, value)| value.clone())
+        };
+
+        let (results, explanation) = index
+            .search_explained("pizza near the corner", false, None, None, &[], false)
+            .await
+            .unwrap();
+        assert!(explanation.near.is_some());
+        assert_eq!(name(&results), Some("Pizza Express".to_string()));
+
+        // "around" means near in English, but here it's part of a name.
+        let (results, explanation) = index
+            .search_explained("pizza around the corner", false, None, None, &[], false)
+            .await
+            .unwrap();
+        assert!(explanation.near.is_none());
+        assert_eq!(name(&results), Some("Pizza Around The Corner".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_multi_word_numbers() {
+        let address = |number: &str, road: &str| {
+            let tags = vec![
+                ("addr:housenumber".to_string(), number.to_string()),
+                ("addr:street".to_string(), road.to_string()),
+            ];
+            ToIndexPoi::new(
+                vec![],
+                Some(number.to_string()),
+                Some(road.to_string()),
+                None,
+                47.6,
+                -122.3,
+                tags,
+            )
+            .unwrap()
+        };
+        let (_dir, index) = test_index(vec![
+            address("10", "21st Street"),
+            address("20", "Twenty Second Street"),
+        ]);
+
+        for (query, number) in [
+            ("10 twenty first street", "10"),
+            ("20 22nd street", "20"),
+            ("20 twenty second street", "20"),
+        ] {
+            let results = index.search(query, false, None, None, &[]).await.unwrap();
+            let (poi, _) = results.first().unwrap_or_else(|| panic!("{}", query));
+            assert!(poi
+                .tags
+                .contains(&("addr:housenumber".to_string(), number.to_string())));
+            assert_eq!(poi.match_type, MatchType::Exact, "{}", query);
+        }
+    }
+}
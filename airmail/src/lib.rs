@@ -4,8 +4,16 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod bbox;
+pub mod confidence;
+pub mod dictionaries;
+pub mod directory;
 pub mod error;
+pub mod explain;
 pub mod index;
+pub mod native;
+pub mod parser;
+pub mod phonetic;
 pub mod poi;
 pub mod query;
 pub mod substitutions;
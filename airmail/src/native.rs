@@ -0,0 +1,129 @@
+use tantivy::tokenizer::{PreTokenizedStream, PreTokenizedString, Token, Tokenizer};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Name the native-script tokenizer is registered under with tantivy.
+pub const NATIVE_TOKENIZER: &str = "native";
+
+/// Normalize text without transliterating it: NFKC so that compatibility forms like full-width
+/// Latin or half-width katakana compare equal to their usual forms, then lowercase.
+pub fn normalize_native(text: &str) -> String {
+    text.nfkc().collect::<String>().to_lowercase()
+}
+
+/// Scripts written without spaces between words. Rather than segmenting them with a
+/// dictionary, runs of these characters are indexed as overlapping bigrams, which matches any
+/// substring of two or more characters as a phrase.
+fn is_unsegmented(c: char) -> bool {
+    matches!(c,
+        '\u{0E00}'..='\u{0EFF}' // Thai, Lao
+        | '\u{1000}'..='\u{109F}' // Myanmar
+        | '\u{1780}'..='\u{17FF}' // Khmer
+        | '\u{3040}'..='\u{30FF}' // Hiragana, Katakana
+        | '\u{31F0}'..='\u{31FF}' // Katakana phonetic extensions
+        | '\u{3400}'..='\u{4DBF}' // CJK extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK unified ideographs
+        | '\u{F900}'..='\u{FAFF}' // CJK compatibility ideographs
+        | '\u{20000}'..='\u{2FFFF}' // CJK extensions B and beyond
+    )
+}
+
+/// Whether text contains any characters from scripts written without spaces.
+pub fn has_unsegmented(text: &str) -> bool {
+    text.chars().any(is_unsegmented)
+}
+
+/// Emit a run of unsegmented graphemes as bigrams, or the grapheme itself if it's alone.
+fn push_bigrams(run: &mut Vec<(usize, &str)>, tokens: &mut Vec<(usize, String)>) {
+    if run.len() == 1 {
+        tokens.push((run[0].0, run[0].1.to_string()));
+    } else {
+        for pair in run.windows(2) {
+            tokens.push((pair[0].0, format!("{}{}", pair[0].1, pair[1].1)));
+        }
+    }
+    run.clear();
+}
+
+/// Split normalized text into words and their byte offsets. Text in scripts without spaces is
+/// split into bigrams instead, see [`is_unsegmented`].
+fn segment(text: &str) -> Vec<(usize, String)> {
+    let mut tokens = Vec::new();
+    let mut run = Vec::new();
+    for (offset, piece) in text.split_word_bound_indices() {
+        if piece.chars().all(is_unsegmented) {
+            run.extend(
+                piece
+                    .grapheme_indices(true)
+                    .map(|(grapheme_offset, grapheme)| (offset + grapheme_offset, grapheme)),
+            );
+            continue;
+        }
+        if !run.is_empty() {
+            push_bigrams(&mut run, &mut tokens);
+        }
+        if piece.chars().any(char::is_alphanumeric) {
+            tokens.push((offset, piece.to_string()));
+        }
+    }
+    if !run.is_empty() {
+        push_bigrams(&mut run, &mut tokens);
+    }
+    tokens
+}
+
+/// Split text into normalized tokens, keeping its original script.
+pub fn native_tokens(text: &str) -> Vec<String> {
+    segment(&normalize_native(text))
+        .into_iter()
+        .map(|(_, token)| token)
+        .collect()
+}
+
+/// Tokenizes text in its original script, so "東京" or "Αθήνα" can be matched as written rather
+/// than through their lossy transliterations.
+#[derive(Clone, Default)]
+pub struct NativeTokenizer;
+
+impl Tokenizer for NativeTokenizer {
+    type TokenStream<'a> = PreTokenizedStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        let text = normalize_native(text);
+        let tokens = segment(&text)
+            .into_iter()
+            .enumerate()
+            .map(|(position, (offset_from, token))| Token {
+                offset_from,
+                offset_to: offset_from + token.len(),
+                position,
+                text: token,
+                position_length: 1,
+            })
+            .collect();
+        PreTokenizedString { text, tokens }.into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::native_tokens;
+
+    #[test]
+    fn test_native_tokens() {
+        assert_eq!(native_tokens("Αθήνα"), vec!["αθήνα"]);
+        assert_eq!(native_tokens("Ｔｏｋｙｏ Tower"), vec!["tokyo", "tower"]);
+        assert_eq!(native_tokens("Москва, Россия"), vec!["москва", "россия"]);
+    }
+
+    #[test]
+    fn test_native_tokens_bigrams() {
+        assert_eq!(native_tokens("北京大学"), vec!["北京", "京大", "大学"]);
+        assert_eq!(
+            native_tokens("東京タワー"),
+            vec!["東京", "京タ", "タワ", "ワー"]
+        );
+        assert_eq!(native_tokens("Tokyo 東"), vec!["tokyo", "東"]);
+        assert_eq!(native_tokens("กรุงเทพ"), vec!["กรุ", "รุง", "งเ", "เท", "ทพ"]);
+    }
+}
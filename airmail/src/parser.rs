@@ -0,0 +1,364 @@
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    dictionaries::{dictionary, numbers, DictionaryCategory, ANY_LANGUAGE, UNIT_CATEGORIES},
+    substitutions::{sanitize, SubstitutionDict},
+};
+
+/// Longest country, region or admin area name that's recognized, in words.
+const MAX_PLACE_WORDS: usize = 4;
+
+/// How many words at the end of a query without commas can be a region or country.
+const TRAILING_PLACE_WORDS: usize = 3;
+
+lazy_static! {
+    static ref COUNTRIES: SubstitutionDict =
+        SubstitutionDict::parse(include_str!("../countries.txt")).unwrap();
+}
+
+/// The part of an address a query token belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressLabel {
+    HouseNumber,
+    Road,
+    Unit,
+    Locality,
+    Region,
+    Postcode,
+    Country,
+    /// Anything else, usually the name or kind of a place, e.g. "space needle" or "cafe".
+    Name,
+}
+
+/// Consecutive query tokens with the same label.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressComponent {
+    pub label: AddressLabel,
+    pub tokens: Vec<String>,
+}
+
+impl AddressComponent {
+    pub fn value(&self) -> String {
+        self.tokens.join(" ")
+    }
+}
+
+/// Split a query into the tokens searched for, including punctuation, which separates address
+/// components.
+pub fn tokenize(query: &str) -> Vec<String> {
+    sanitize(query)
+        .split_word_bounds()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn is_punctuation(token: &str) -> bool {
+    token
+        .chars()
+        .all(|c| c.is_whitespace() || c.is_ascii_punctuation())
+}
+
+fn has_digit(token: &str) -> bool {
+    token.chars().any(|c| c.is_ascii_digit())
+}
+
+fn in_dictionary(categories: &[DictionaryCategory], phrase: &str) -> bool {
+    dictionary(ANY_LANGUAGE, categories)
+        .canonical(phrase)
+        .is_some()
+}
+
+/// Whether a token is an ordinal like "5th" or "125th", which is part of a street name rather
+/// than a number of its own. Only the last two digits are looked up, since that's what the
+/// suffix depends on.
+fn is_ordinal(token: &str) -> bool {
+    let Some(suffix_start) = token.find(|c: char| !c.is_ascii_digit()) else {
+        return false;
+    };
+    let (digits, suffix) = token.split_at(suffix_start);
+    let numbers = numbers(ANY_LANGUAGE);
+    !digits.is_empty()
+        && suffix.chars().all(char::is_alphabetic)
+        && [2, 1].iter().any(|len| {
+            let start = digits.len().saturating_sub(*len);
+            numbers
+                .canonical(&format!("{}{}", &digits[start..], suffix))
+                .is_some()
+        })
+}
+
+/// Postcodes are either long numbers like "98103", or mixes of letters and digits starting
+/// with a letter like "sw1a" or "h3b".
+fn looks_like_postcode(token: &str) -> bool {
+    let digits = token.chars().filter(char::is_ascii_digit).count();
+    let letters = token.chars().filter(|c| c.is_alphabetic()).count();
+    let starts_with_letter = token.chars().next().is_some_and(char::is_alphabetic);
+    (digits >= 4 && letters == 0) || (digits > 0 && starts_with_letter && token.len() >= 3)
+}
+
+/// Label the tokens of a query with the parts of an address they most likely are, using the
+/// dictionaries, numeric patterns and `is_admin`, which tells whether a phrase is the name of an
+/// admin area. Punctuation tokens aren't part of any component.
+pub fn parse_tokens(tokens: &[String], is_admin: impl Fn(&str) -> bool) -> Vec<AddressComponent> {
+    let mut labels: Vec<Option<AddressLabel>> = vec![None; tokens.len()];
+    let words: Vec<usize> = (0..tokens.len())
+        .filter(|i| !is_punctuation(&tokens[*i]))
+        .collect();
+    let Some(&first_word) = words.first() else {
+        return Vec::new();
+    };
+
+    // Regions and countries come last: after the first comma, or in the last few words.
+    let first_comma = tokens.iter().position(|token| token == ",");
+    let trailing_start = first_comma.unwrap_or_else(|| {
+        words[words.len().saturating_sub(TRAILING_PLACE_WORDS)].max(first_word + 1)
+    });
+    label_places(tokens, &mut labels, trailing_start);
+
+    // Units are a unit type followed by a number or letter, e.g. "apt 4" or "unit b".
+    for pair in words.windows(2) {
+        let (unit_type, unit) = (&tokens[pair[0]], &tokens[pair[1]]);
+        if labels[pair[0]].is_none()
+            && in_dictionary(UNIT_CATEGORIES, unit_type)
+            && (has_digit(unit) || unit.chars().count() == 1)
+        {
+            labels[pair[0]] = Some(AddressLabel::Unit);
+            labels[pair[1]] = Some(AddressLabel::Unit);
+        }
+    }
+    if let Some(pair) = tokens.windows(2).position(|pair| pair[0] == "#") {
+        if has_digit(&tokens[pair + 1]) {
+            labels[pair + 1] = Some(AddressLabel::Unit);
+        }
+    }
+
+    // Numbers are house numbers, unless they come after something else and look like postcodes.
+    for &i in &words {
+        if labels[i].is_some() || !has_digit(&tokens[i]) || is_ordinal(&tokens[i]) {
+            continue;
+        }
+        labels[i] = if i > first_word && looks_like_postcode(&tokens[i]) {
+            Some(AddressLabel::Postcode)
+        } else {
+            Some(AddressLabel::HouseNumber)
+        };
+    }
+
+    label_words(tokens, &mut labels, first_word, is_admin);
+    let mut components = group(tokens, &labels);
+
+    // Without a locality, a region's full name is more likely a city of the same name, as in
+    // "barcelona" or "new york".
+    if !components
+        .iter()
+        .any(|component| component.label == AddressLabel::Locality)
+    {
+        let toponyms = dictionary(ANY_LANGUAGE, &[DictionaryCategory::Toponyms]);
+        if let Some(region) = components.iter_mut().find(|component| {
+            let value = component.value();
+            component.label == AddressLabel::Region && toponyms.canonical(&value) == Some(&value)
+        }) {
+            region.label = AddressLabel::Locality;
+        }
+    }
+    components
+}
+
+/// Group consecutive tokens with the same label into components.
+fn group(tokens: &[String], labels: &[Option<AddressLabel>]) -> Vec<AddressComponent> {
+    let mut components: Vec<AddressComponent> = Vec::new();
+    let mut previous = None;
+    for (i, token) in tokens.iter().enumerate() {
+        let Some(label) = labels[i] else {
+            previous = None;
+            continue;
+        };
+        match components.last_mut() {
+            Some(component) if previous == Some(label) => component.tokens.push(token.clone()),
+            _ => components.push(AddressComponent {
+                label,
+                tokens: vec![token.clone()],
+            }),
+        }
+        previous = Some(label);
+    }
+    components
+}
+
+/// Label regions and countries at or after `start`, preferring the longest match.
+fn label_places(tokens: &[String], labels: &mut [Option<AddressLabel>], start: usize) {
+    let mut i = start;
+    while i < tokens.len() {
+        let longest = (1..=MAX_PLACE_WORDS.min(tokens.len() - i))
+            .rev()
+            .find_map(|len| {
+                let span = &tokens[i..i + len];
+                if span.iter().any(|token| is_punctuation(token)) {
+                    return None;
+                }
+                let phrase = span.join(" ");
+                // Many stopwords are also abbreviations, like "de" for Delaware.
+                if in_dictionary(&[DictionaryCategory::Stopwords], &phrase) {
+                    return None;
+                }
+                if in_dictionary(&[DictionaryCategory::Toponyms], &phrase) {
+                    Some((len, AddressLabel::Region))
+                } else if COUNTRIES.canonical(&phrase).is_some() {
+                    Some((len, AddressLabel::Country))
+                } else {
+                    None
+                }
+            });
+        if let Some((len, label)) = longest {
+            labels[i..i + len].fill(Some(label));
+            i += len;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Label the remaining runs of words as roads, localities or names.
+fn label_words(
+    tokens: &[String],
+    labels: &mut [Option<AddressLabel>],
+    first_word: usize,
+    is_admin: impl Fn(&str) -> bool,
+) {
+    let mut i = 0;
+    while i < tokens.len() {
+        if labels[i].is_some() || is_punctuation(&tokens[i]) {
+            i += 1;
+            continue;
+        }
+        let mut end = i;
+        while end < tokens.len() && labels[end].is_none() && !is_punctuation(&tokens[end]) {
+            end += 1;
+        }
+
+        let next_to_number = |j: usize| {
+            labels.get(j) == Some(&Some(AddressLabel::HouseNumber))
+                || (j > 0 && labels[j - 1] == Some(AddressLabel::HouseNumber))
+        };
+        let is_street_type = |j: usize| {
+            in_dictionary(&[DictionaryCategory::StreetTypes], &tokens[j])
+                && !in_dictionary(&[DictionaryCategory::Directionals], &tokens[j])
+        };
+        // A street type after the name runs up to any directionals after it, e.g. "fremont ave
+        // n". One before the name, like "carrer de villarroel", could as well be the first word
+        // of a place name like "space needle", so it takes a house number or a stopword after it
+        // to tell.
+        let suffix_type = (i + 1..end).find(|j| is_street_type(*j));
+        let prefix_type = is_street_type(i)
+            && (next_to_number(i)
+                || next_to_number(end)
+                || tokens
+                    .get(i + 1)
+                    .is_some_and(|token| in_dictionary(&[DictionaryCategory::Stopwords], token)));
+        // Where a trailing admin area starts, as in "rue de rivoli paris" or "space needle
+        // seattle".
+        let admin_start = || (i + 1..end).find(|j| is_admin(&tokens[*j..end].join(" ")));
+        let road_end = if let Some(mut j) = suffix_type {
+            j += 1;
+            while j < end && in_dictionary(&[DictionaryCategory::Directionals], &tokens[j]) {
+                j += 1;
+            }
+            Some(j)
+        } else if prefix_type {
+            admin_start().or(Some(end))
+        } else {
+            None
+        };
+
+        let (label, run_end) = if let Some(road_end) = road_end {
+            (AddressLabel::Road, road_end)
+        } else if next_to_number(i) || next_to_number(end) {
+            (AddressLabel::Road, end)
+        } else if is_admin(&tokens[i..end].join(" ")) {
+            (AddressLabel::Locality, end)
+        } else if i == first_word {
+            (AddressLabel::Name, admin_start().unwrap_or(end))
+        } else {
+            (AddressLabel::Locality, end)
+        };
+        labels[i..run_end].fill(Some(label));
+        i = run_end;
+    }
+}
+
+/// Parse a free-text query into address components. See [`parse_tokens`].
+pub fn parse(query: &str, is_admin: impl Fn(&str) -> bool) -> Vec<AddressComponent> {
+    parse_tokens(&tokenize(query), is_admin)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, AddressLabel};
+
+    fn labels(query: &str) -> Vec<(AddressLabel, String)> {
+        parse(query, |phrase| phrase == "seattle" || phrase == "paris")
+            .into_iter()
+            .map(|component| (component.label, component.value()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_us_address() {
+        assert_eq!(
+            labels("123 Fremont Ave N Apt 4, Seattle, WA 98103, USA"),
+            vec![
+                (AddressLabel::HouseNumber, "123".to_string()),
+                (AddressLabel::Road, "fremont ave n".to_string()),
+                (AddressLabel::Unit, "apt 4".to_string()),
+                (AddressLabel::Locality, "seattle".to_string()),
+                (AddressLabel::Region, "wa".to_string()),
+                (AddressLabel::Postcode, "98103".to_string()),
+                (AddressLabel::Country, "usa".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_european_address() {
+        assert_eq!(
+            labels("Carrer de Villarroel 10 Barcelona"),
+            vec![
+                (AddressLabel::Road, "carrer de villarroel".to_string()),
+                (AddressLabel::HouseNumber, "10".to_string()),
+                (AddressLabel::Locality, "barcelona".to_string()),
+            ]
+        );
+        assert_eq!(
+            labels("rue de rivoli paris"),
+            vec![
+                (AddressLabel::Road, "rue de rivoli".to_string()),
+                (AddressLabel::Locality, "paris".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_ordinal_street() {
+        assert_eq!(
+            labels("725 5th Ave"),
+            vec![
+                (AddressLabel::HouseNumber, "725".to_string()),
+                (AddressLabel::Road, "5th ave".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_place() {
+        assert_eq!(
+            labels("space needle seattle"),
+            vec![
+                (AddressLabel::Name, "space needle".to_string()),
+                (AddressLabel::Locality, "seattle".to_string()),
+            ]
+        );
+    }
+}
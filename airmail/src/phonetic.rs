@@ -0,0 +1,112 @@
+use rphonetic::DoubleMetaphone;
+use tantivy::tokenizer::{PreTokenizedStream, PreTokenizedString, Token, Tokenizer};
+
+/// Name the phonetic tokenizer is registered under with tantivy.
+pub const PHONETIC_TOKENIZER: &str = "phonetic";
+
+lazy_static! {
+    static ref DOUBLE_METAPHONE: DoubleMetaphone = DoubleMetaphone::default();
+}
+
+/// The Double Metaphone codes of a word, primary first. Words with digits don't have a
+/// pronunciation worth matching on, so they have no codes.
+pub fn phonetic_codes(word: &str) -> Vec<String> {
+    let word = deunicode::deunicode(word);
+    if word.is_empty() || word.chars().any(|c| c.is_numeric()) {
+        return Vec::new();
+    }
+    let result = DOUBLE_METAPHONE.double_metaphone(&word);
+    let mut codes = vec![result.primary()];
+    if result.alternate() != codes[0] {
+        codes.push(result.alternate());
+    }
+    codes.retain(|code| !code.is_empty());
+    codes
+}
+
+/// The words of `text`, split on anything that isn't alphanumeric, with their byte offsets.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.char_indices()
+        .filter(|(start, c)| {
+            c.is_alphanumeric()
+                && !text[..*start]
+                    .chars()
+                    .next_back()
+                    .is_some_and(char::is_alphanumeric)
+        })
+        .map(|(start, _)| {
+            let end = text[start..]
+                .find(|c: char| !c.is_alphanumeric())
+                .map_or(text.len(), |len| start + len);
+            (start, &text[start..end])
+        })
+}
+
+/// Tokenizes text into the Double Metaphone codes of its words, so names with many spellings
+/// (Mohammed/Muhammad, Tchaikovsky/Chaikovsky) index to the same terms. A word's primary and
+/// alternate codes share a position.
+#[derive(Clone, Default)]
+pub struct PhoneticTokenizer;
+
+impl Tokenizer for PhoneticTokenizer {
+    type TokenStream<'a> = PreTokenizedStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        let mut tokens = Vec::new();
+        let mut position = 0;
+        for (offset_from, word) in words(text) {
+            let codes = phonetic_codes(word);
+            if codes.is_empty() {
+                continue;
+            }
+            for code in codes {
+                tokens.push(Token {
+                    offset_from,
+                    offset_to: offset_from + word.len(),
+                    position,
+                    text: code,
+                    position_length: 1,
+                });
+            }
+            position += 1;
+        }
+        PreTokenizedString {
+            text: text.to_string(),
+            tokens,
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tantivy::tokenizer::{TokenStream, Tokenizer};
+
+    use super::{phonetic_codes, PhoneticTokenizer};
+
+    #[test]
+    fn test_phonetic_codes() {
+        assert_eq!(phonetic_codes("Mohammed"), phonetic_codes("Muhammad"));
+        assert_eq!(
+            phonetic_codes("Tchaikovsky")[0],
+            phonetic_codes("Chaikovsky")[0]
+        );
+        assert!(phonetic_codes("Schmidt")
+            .iter()
+            .any(|code| phonetic_codes("Smith").contains(code)));
+        assert!(phonetic_codes("123").is_empty());
+    }
+
+    #[test]
+    fn test_phonetic_offsets() {
+        let text = "Café – Müller  Straße";
+        let mut tokenizer = PhoneticTokenizer;
+        let mut stream = tokenizer.token_stream(text);
+        let mut words = Vec::new();
+        stream.process(&mut |token| {
+            words.push((&text[token.offset_from..token.offset_to], token.position))
+        });
+        words.dedup();
+        assert_eq!(words, [("Café", 0), ("Müller", 1), ("Straße", 2)]);
+    }
+}
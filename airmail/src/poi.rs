@@ -1,8 +1,29 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
 use anyhow::Result;
-use lingua::Language;
+use itertools::Itertools;
+use lingua::{Language, LanguageDetector, LanguageDetectorBuilder};
+use log::warn;
 use serde::{Deserialize, Serialize};
 
-use crate::substitutions::permute_road;
+use crate::{
+    confidence::MatchType,
+    dictionaries::{
+        language_code, DictionaryCategory, LEVEL_CATEGORIES, NAME_CATEGORIES, ROAD_CATEGORIES,
+        TOPONYM_CATEGORIES, UNIT_CATEGORIES,
+    },
+    substitutions::{max_permutations, normalize_numbers, permute_within, sanitize},
+};
+
+thread_local! {
+    /// Language detectors are cheap to use but not to build, and there are only so many
+    /// combinations of spoken languages, so keep one around per combination.
+    static LANGUAGE_DETECTORS: RefCell<HashMap<Vec<Language>, LanguageDetector>> =
+        RefCell::new(HashMap::new());
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AirmailPoi {
@@ -12,11 +33,22 @@ pub struct AirmailPoi {
     pub lat: f64,
     pub lng: f64,
     pub tags: Vec<(String, String)>,
+    /// Whether this is an address synthesized from an `addr:interpolation` way rather than
+    /// one that was mapped individually.
+    #[serde(default)]
+    pub interpolated: bool,
+    /// How well this result matched its query, from 0 to 1. Unlike search scores, confidences
+    /// can be compared across queries.
+    #[serde(default)]
+    pub confidence: f64,
+    #[serde(default)]
+    pub match_type: MatchType,
 }
 
 impl AirmailPoi {
     pub fn new(source: String, lat: f64, lng: f64, tags: Vec<(String, String)>) -> Result<Self> {
         let s2cell = s2::cellid::CellID::from(s2::latlng::LatLng::from_degrees(lat, lng)).0;
+        let interpolated = tags.iter().any(|(key, _)| key == "addr:interpolation");
 
         Ok(Self {
             source,
@@ -25,6 +57,9 @@ impl AirmailPoi {
             lat,
             lng,
             tags,
+            interpolated,
+            confidence: 0.0,
+            match_type: MatchType::default(),
         })
     }
 }
@@ -35,10 +70,14 @@ pub struct ToIndexPoi {
     pub house_number: Option<String>,
     pub road: Option<String>,
     pub unit: Option<String>,
+    pub level: Option<String>,
     pub admins: Vec<String>,
     pub s2cell: u64,
     pub tags: Vec<(String, String)>,
     pub languages: Vec<Language>,
+    /// How prominent this POI is, from 0 to 1. Used to break ties between otherwise similar
+    /// matches, e.g. so the capital outranks a bus stop with the same name.
+    pub importance: f64,
 }
 
 impl ToIndexPoi {
@@ -58,33 +97,148 @@ impl ToIndexPoi {
             house_number,
             road,
             unit,
+            level: None,
             admins: Vec::new(),
             s2cell,
             tags,
             languages: Vec::new(),
+            importance: 0.0,
         })
     }
 }
 
+impl ToIndexPoi {
+    /// Narrow `languages` down to the ones the POI's road and name are actually written in.
+    ///
+    /// `languages` is typically populated with every language spoken in the POI's country,
+    /// which in bilingual places means applying substitutions that don't make sense for the
+    /// text, e.g. French street types to a Dutch street name in Brussels. If detection is
+    /// inconclusive the languages are left as they are.
+    pub fn detect_languages(&mut self) {
+        let mut candidates = self.languages.clone();
+        candidates.sort();
+        candidates.dedup();
+        if candidates.len() < 2 {
+            return;
+        }
+
+        let name = self
+            .tags
+            .iter()
+            .find(|(key, _)| key == "name")
+            .map(|(_, value)| value.as_str());
+        let texts = self.road.as_deref().into_iter().chain(name);
+
+        let detected = LANGUAGE_DETECTORS.with(|detectors| {
+            let mut detectors = detectors.borrow_mut();
+            let detector = detectors.entry(candidates.clone()).or_insert_with(|| {
+                LanguageDetectorBuilder::from_languages(&candidates)
+                    .with_minimum_relative_distance(0.05)
+                    .build()
+            });
+            let mut detected: Vec<Language> = texts
+                .filter_map(|text| detector.detect_language_of(text))
+                .collect();
+            detected.sort();
+            detected.dedup();
+            detected
+        });
+
+        if !detected.is_empty() {
+            self.languages = detected;
+        }
+    }
+}
+
 pub struct SchemafiedPoi {
     pub content: Vec<String>,
+    /// The POI's names as they were written, for phonetic matching.
+    pub names: Vec<String>,
+    /// Names and admin areas in their original script, before transliteration.
+    pub native: Vec<String>,
+    /// Admin area names and their permutations, for recognizing localities in queries.
+    pub admins: Vec<String>,
+    /// Admin area names as they were written, stored to tell whether a result is in a locality
+    /// named in its query.
+    pub admin_names: Vec<String>,
     pub s2cell: u64,
     pub s2cell_parents: Vec<u64>,
     pub tags: Vec<(String, String)>,
+    pub importance: f64,
+}
+
+/// Expand a field into its permutations in each of the POI's languages, always including the
+/// sanitized field itself even if no languages are known. Numbers written as words are also
+/// indexed in digits, see [`normalize_numbers`].
+///
+/// The languages share one budget of [`max_permutations`], so that a POI in a country with
+/// several languages doesn't index several times as many permutations of each field.
+fn permutations(
+    field: &str,
+    languages: &[Language],
+    categories: &[DictionaryCategory],
+) -> Vec<String> {
+    let sanitized = sanitize(field);
+    let mut permutations = vec![sanitized.clone()];
+    let share = max_permutations() / languages.len().max(1);
+    for lang in languages {
+        // The sanitized field is indexed regardless, so a field that can't be permuted is
+        // still found by its name as written.
+        let permute = |text: &str, limit: usize| {
+            permute_within(text, lang, categories, limit).unwrap_or_else(|err| {
+                warn!("Failed to permute {:?}: {}", text, err);
+                Vec::new()
+            })
+        };
+        let mut language_permutations = permute(field, share);
+        // The digits are always indexed, even if the words used up the language's share.
+        let normalized = normalize_numbers(field, &language_code(lang));
+        if normalized != sanitized {
+            let remaining = share.saturating_sub(language_permutations.len());
+            language_permutations.extend(permute(&normalized, remaining));
+        }
+        permutations.extend(language_permutations);
+    }
+    permutations.into_iter().unique().collect()
 }
 
 impl From<ToIndexPoi> for SchemafiedPoi {
     fn from(poi: ToIndexPoi) -> Self {
+        // Only the POI's primary name is permuted. Its other names are mostly translations,
+        // which would multiply the size of the index for little gain, so they're indexed as
+        // they're written.
+        let primary_name = poi
+            .tags
+            .iter()
+            .find(|(key, _)| key == "name")
+            .map(|(_, value)| value)
+            .or(poi.names.first());
         let mut content = Vec::new();
-        content.extend(poi.names);
+        for name in &poi.names {
+            let categories = if Some(name) == primary_name {
+                NAME_CATEGORIES
+            } else {
+                &[]
+            };
+            content.extend(permutations(name, &poi.languages, categories));
+        }
         content.extend(poi.house_number);
-        if let Some(road) = poi.road {
-            for lang in poi.languages {
-                content.extend(permute_road(&road, &lang).expect("Failed to permute road"));
-            }
+        if let Some(road) = &poi.road {
+            content.extend(permutations(road, &poi.languages, ROAD_CATEGORIES));
+        }
+        if let Some(unit) = &poi.unit {
+            content.extend(permutations(unit, &poi.languages, UNIT_CATEGORIES));
         }
-        content.extend(poi.unit);
-        content.extend(poi.admins);
+        if let Some(level) = &poi.level {
+            content.extend(permutations(level, &poi.languages, LEVEL_CATEGORIES));
+        }
+        let mut admins = Vec::new();
+        for admin in &poi.admins {
+            admins.extend(permutations(admin, &poi.languages, TOPONYM_CATEGORIES));
+        }
+        content.extend(admins.iter().cloned());
+        let mut seen = HashSet::new();
+        content.retain(|field| seen.insert(field.clone()));
 
         let mut s2cell_parents = Vec::new();
         let cell = s2::cellid::CellID(poi.s2cell);
@@ -93,11 +247,82 @@ impl From<ToIndexPoi> for SchemafiedPoi {
             s2cell_parents.push(cell.0);
         }
 
+        let native = poi.names.iter().chain(&poi.admins).cloned().collect();
+
         Self {
             content,
+            native,
+            admins,
+            admin_names: poi.admins,
+            names: poi.names,
             s2cell: poi.s2cell,
             s2cell_parents,
             tags: poi.tags,
+            importance: poi.importance,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use lingua::Language;
+
+    use crate::{
+        poi::{SchemafiedPoi, ToIndexPoi},
+        substitutions::max_permutations,
+    };
+
+    #[test]
+    fn test_detect_languages() {
+        let mut poi = ToIndexPoi::new(
+            vec![],
+            Some("1200".to_string()),
+            Some("Rue Sainte-Catherine Ouest".to_string()),
+            None,
+            45.5,
+            -73.57,
+            vec![],
+        )
+        .unwrap();
+        poi.languages = vec![Language::English, Language::French];
+        poi.detect_languages();
+        assert_eq!(poi.languages, vec![Language::French]);
+    }
+
+    #[test]
+    fn test_permutation_budget() {
+        let names = vec![
+            "Saint Mary Church".to_string(),
+            "Church of Saint Mary".to_string(),
+        ];
+        let tags = vec![("name".to_string(), "Saint Mary Church".to_string())];
+        let mut poi = ToIndexPoi::new(
+            names,
+            None,
+            Some("North East Saint Mary Street Avenue".to_string()),
+            None,
+            41.4,
+            2.17,
+            tags,
+        )
+        .unwrap();
+        poi.languages = vec![
+            Language::English,
+            Language::Spanish,
+            Language::Catalan,
+            Language::French,
+        ];
+        let poi = SchemafiedPoi::from(poi);
+        assert!(poi.content.contains(&"saint mary chr".to_string()));
+        // Names other than the primary one aren't permuted.
+        assert!(poi.content.contains(&"church of saint mary".to_string()));
+        assert!(!poi.content.contains(&"chr of saint mary".to_string()));
+        // Every language's permutations of the road together stay within the budget.
+        let roads = poi
+            .content
+            .iter()
+            .filter(|text| text.contains("mary") && !text.contains("church"))
+            .count();
+        assert!(roads <= max_permutations() + 1, "{}", roads);
+    }
+}
@@ -1,11 +1,350 @@
+use std::sync::Arc;
+
 use itertools::Itertools;
+use lingua::{Language, LanguageDetector, LanguageDetectorBuilder};
+use serde::Serialize;
+
+use crate::{
+    dictionaries::{
+        dictionary, language_code, DictionaryCategory, ANY_LANGUAGE, LEVEL_CATEGORIES,
+        NAME_CATEGORIES, ROAD_CATEGORIES, TOPONYM_CATEGORIES, UNIT_CATEGORIES,
+    },
+    parser::AddressComponent,
+    substitutions::{normalize_numbers, sanitize, SubstitutionDict},
+};
+
+/// Longest phrase in the `near` dictionaries, e.g. "a proximite de moi".
+const MAX_NEAR_WORDS: usize = 4;
+
+/// Upper bound on the number of alternatives a single query token expands into. Short
+/// abbreviations like "st" mean something different in nearly every language.
+const MAX_TOKEN_EXPANSIONS: usize = 16;
+
+/// How confident language detection has to be in the most likely language of a query for the
+/// query's language to be considered known at all. Short queries in no particular language score
+/// about the same in every language.
+const MIN_LANGUAGE_CONFIDENCE: f64 = 0.2;
+
+/// How confident language detection has to be in a language, relative to the language it's most
+/// confident in, for a query to be considered written in it.
+const MIN_RELATIVE_LANGUAGE_CONFIDENCE: f64 = 0.5;
+
+lazy_static! {
+    static ref QUERY_LANGUAGE_DETECTOR: LanguageDetector =
+        LanguageDetectorBuilder::from_all_languages().with_low_accuracy_mode().build();
+    /// Every category of substitution the indexer might have applied.
+    static ref QUERY_CATEGORIES: Vec<DictionaryCategory> = [
+        ROAD_CATEGORIES,
+        UNIT_CATEGORIES,
+        LEVEL_CATEGORIES,
+        NAME_CATEGORIES,
+        TOPONYM_CATEGORIES,
+    ]
+    .concat();
+}
+
+/// Upper bound on the number of multi-token phrase clauses in a query plan.
+const MAX_PHRASES: usize = 16;
+
+/// Plan the clauses of a query: every distinct token on its own, followed by a bounded set of
+/// phrases from within each parsed address component. Phrases never span components, since the
+/// indexed values they'd have to match (house number, street, admin areas) are stored separately
+/// anyway. Longer phrases are preferred as they're the most specific.
+pub fn plan_query(components: &[AddressComponent]) -> Vec<Vec<String>> {
+    let mut plan: Vec<Vec<String>> = components
+        .iter()
+        .flat_map(|component| &component.tokens)
+        .unique()
+        .map(|token| vec![token.clone()])
+        .collect();
+
+    let longest = components
+        .iter()
+        .map(|component| component.tokens.len())
+        .max()
+        .unwrap_or_default();
+    let mut phrases = Vec::new();
+    for len in (2..=longest).rev() {
+        for component in components {
+            for phrase in component.tokens.windows(len) {
+                if phrases.len() < MAX_PHRASES && !phrases.contains(&phrase) {
+                    phrases.push(phrase);
+                }
+            }
+        }
+    }
+    plan.extend(phrases.into_iter().map(<[String]>::to_vec));
+    plan
+}
+
+/// The languages whose number words apply to a query in `languages`, by language code: those
+/// languages, or every language if it isn't known which the query is in. Each language's numbers
+/// have to be applied on their own, since merged tables would only keep one meaning of words like
+/// "nove", which is "9" in Italian but "9e" (ninth) in Catalan.
+pub(crate) fn number_languages(languages: &[Language]) -> Vec<String> {
+    if languages.is_empty() {
+        Language::all().iter().sorted().map(language_code).collect()
+    } else {
+        languages.iter().map(language_code).collect()
+    }
+}
+
+/// Rewrite numbers written as words in the tokens of an address component into digits, e.g.
+/// "twenty first avenue" into "21st avenue", to match how the indexer normalizes whole fields.
+/// Multi-word numbers only match that way, since "twenty" and "first" don't on their own. The
+/// first of `languages`, which are the ones the query might be in, that changes anything wins.
+pub fn normalize_query_numbers(tokens: &[String], languages: &[Language]) -> Vec<String> {
+    let text = tokens.join(" ");
+    languages
+        .iter()
+        .map(|language| normalize_numbers(&text, &language_code(language)))
+        .find(|normalized| *normalized != text)
+        .map_or_else(
+            || tokens.to_vec(),
+            |normalized| {
+                normalized
+                    .split_ascii_whitespace()
+                    .map(str::to_string)
+                    .collect()
+            },
+        )
+}
+
+/// The dictionaries of `categories` for a query in `languages`, or the merged dictionary of every
+/// language if it isn't known which the query is in.
+fn query_dictionaries(
+    languages: &[Language],
+    categories: &[DictionaryCategory],
+) -> Vec<Arc<SubstitutionDict>> {
+    if languages.is_empty() {
+        vec![dictionary(ANY_LANGUAGE, categories)]
+    } else {
+        languages
+            .iter()
+            .map(|language| dictionary(&language_code(language), categories))
+            .collect()
+    }
+}
+
+/// Expand a query token into the abbreviations and expansions it could stand for, e.g. "st"
+/// into "street" and "saint", or "fifth" into "5th". The token itself is not included.
+///
+/// Numbers and abbreviations are taken from the dictionaries of `languages`, the ones the query
+/// might be in, or of every language if it isn't known, see [`number_languages`]. The same
+/// abbreviation often stands for different words in different languages.
+pub fn expand_token(token: &str, languages: &[Language]) -> Vec<String> {
+    let token = sanitize(token);
+    let abbreviations = query_dictionaries(languages, &QUERY_CATEGORIES);
+    number_languages(languages)
+        .iter()
+        .map(|language| normalize_numbers(&token, language))
+        .chain(
+            abbreviations
+                .iter()
+                .flat_map(|dictionary| dictionary.substitute(&token)),
+        )
+        .filter(|alternative| *alternative != token)
+        .unique()
+        .take(MAX_TOKEN_EXPANSIONS)
+        .collect()
+}
+
+/// How many typos to tolerate in a query token, scaled by its length: none up to 3 characters,
+/// one up to 7, and two beyond that. Tokens with digits are usually house numbers or postcodes
+/// where a typo means a different place, so they must match exactly.
+///
+/// Fuzzy queries against a remote index fetch a lot of the term dictionary over HTTP, so there
+/// only long tokens get a single edit.
+pub fn max_edit_distance(token: &str, remote: bool) -> u8 {
+    if token.chars().any(|c| c.is_numeric()) {
+        return 0;
+    }
+    match (token.chars().count(), remote) {
+        (0..=7, true) => 0,
+        (_, true) => 1,
+        (0..=3, false) => 0,
+        (4..=7, false) => 1,
+        (_, false) => 2,
+    }
+}
+
+/// Whether a query token is a stopword, like "the" or "de", in one of `languages`, the ones the
+/// query might be written in. Documents often lack these, so they shouldn't be required to match.
+///
+/// Stopwords in one language are often names in another, like "die" or "la", so nothing is a
+/// stopword in a query whose language isn't known.
+pub fn is_stopword(token: &str, languages: &[Language]) -> bool {
+    let token = sanitize(token);
+    languages.iter().any(|language| {
+        dictionary(&language_code(language), &[DictionaryCategory::Stopwords])
+            .canonical(&token)
+            .is_some()
+    })
+}
 
-pub(crate) fn all_subsequences(tokens: &[String]) -> Vec<Vec<String>> {
-    let mut subsequences: Vec<Vec<String>> = Vec::new();
-    for i in 0..tokens.len() {
-        for j in i..tokens.len() {
-            subsequences.push(tokens[i..=j].iter().map(|s| s.to_string()).collect_vec());
+/// A query of the form "<subject> near <anchor>", e.g. "cafe near pike place".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NearQuery {
+    pub subject: String,
+    pub anchor: String,
+}
+
+/// The languages a query might be written in, or none if it can't be told. A handful of words is
+/// rarely enough to tell one language from its neighbours, so every language detection is about
+/// as confident in as the most likely one is a candidate.
+pub fn query_languages(query: &str) -> Vec<Language> {
+    let confidences = QUERY_LANGUAGE_DETECTOR.compute_language_confidence_values(query);
+    let Some((_, top)) = confidences.first().copied() else {
+        return Vec::new();
+    };
+    if top < MIN_LANGUAGE_CONFIDENCE {
+        return Vec::new();
+    }
+    confidences
+        .into_iter()
+        .filter(|(_, confidence)| *confidence >= top * MIN_RELATIVE_LANGUAGE_CONFIDENCE)
+        .map(|(language, _)| language)
+        .collect()
+}
+
+/// Split a query around a phrase meaning "near" in one of `languages`, if it has one with text
+/// on both sides.
+///
+/// Near phrases are short and often ordinary words in other languages, like Danish "om" or German
+/// "nah", so only the languages the query is likely written in are considered, see
+/// [`query_languages`]. Phrases that are also stopwords, like "in" or "a", are ignored because
+/// they're too ambiguous to be split on.
+pub fn split_near(query: &str, languages: &[Language]) -> Option<NearQuery> {
+    let query = sanitize(query);
+    let tokens: Vec<&str> = query.split_ascii_whitespace().collect();
+    let dictionaries: Vec<_> = languages
+        .iter()
+        .map(|language| dictionary(&language_code(language), &[DictionaryCategory::Near]))
+        .collect();
+    for start in 1..tokens.len() {
+        for len in (1..=MAX_NEAR_WORDS.min(tokens.len() - start)).rev() {
+            let phrase = tokens[start..start + len].join(" ");
+            let is_near = dictionaries
+                .iter()
+                .any(|near| near.canonical(&phrase).is_some());
+            if is_near && !is_stopword(&phrase, languages) {
+                // Phrases like "near me" refer to the user's location, which we don't have.
+                if start + len == tokens.len() {
+                    return None;
+                }
+                return Some(NearQuery {
+                    subject: tokens[..start].join(" "),
+                    anchor: tokens[start + len..].join(" "),
+                });
+            }
         }
     }
-    subsequences
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use lingua::Language;
+
+    use super::{
+        expand_token, is_stopword, max_edit_distance, normalize_query_numbers, plan_query,
+        query_languages, split_near, NearQuery,
+    };
+    use crate::parser::parse;
+
+    fn tokens(text: &str) -> Vec<String> {
+        text.split_whitespace().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_plan_query_is_bounded() {
+        let query = tokens("1600 pennsylvania avenue northwest , washington district of columbia 20500 united states of america");
+        let plan = plan_query(&parse(&query.join(" "), |_| false));
+        assert!(plan.len() < query.len() * (query.len() + 1) / 2);
+        assert!(plan.contains(&tokens("pennsylvania avenue northwest")));
+        assert!(!plan.iter().any(|clause| clause.contains(&",".to_string())));
+    }
+
+    #[test]
+    fn test_expand_token() {
+        let alternatives = expand_token("Ave", &[Language::English]);
+        assert!(alternatives.contains(&"avenue".to_string()));
+        assert!(!alternatives.contains(&"ave".to_string()));
+        assert!(expand_token("fifth", &[Language::English]).contains(&"5th".to_string()));
+        assert!(expand_token("fifth", &[]).contains(&"5th".to_string()));
+        // "nove" is 9 in Italian, but ninth in Catalan.
+        assert!(expand_token("nove", &[Language::Italian]).contains(&"9".to_string()));
+        assert!(!expand_token("nove", &[Language::Italian]).contains(&"9e".to_string()));
+        assert!(expand_token("nove", &[Language::Catalan]).contains(&"9e".to_string()));
+        assert!(expand_token("nove", &[]).contains(&"9".to_string()));
+        // "str" is "strasse" in German, but "street" in English.
+        let english = expand_token("str", &[Language::English]);
+        assert!(english.contains(&"street".to_string()));
+        assert!(!english.contains(&"strasse".to_string()));
+        assert!(expand_token("str", &[Language::German]).contains(&"strasse".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_query_numbers() {
+        assert_eq!(
+            normalize_query_numbers(&tokens("twenty first avenue"), &[Language::English]),
+            tokens("21st avenue")
+        );
+        assert_eq!(
+            normalize_query_numbers(
+                &tokens("calle diez"),
+                &[Language::English, Language::Spanish]
+            ),
+            tokens("calle 10")
+        );
+        assert_eq!(
+            normalize_query_numbers(&tokens("twenty first avenue"), &[]),
+            tokens("twenty first avenue")
+        );
+    }
+
+    #[test]
+    fn test_max_edit_distance() {
+        assert_eq!(max_edit_distance("ave", false), 0);
+        assert_eq!(max_edit_distance("seatle", false), 1);
+        assert_eq!(max_edit_distance("brodway", false), 1);
+        assert_eq!(max_edit_distance("tchaikovsky", false), 2);
+        assert_eq!(max_edit_distance("12345", false), 0);
+        assert_eq!(max_edit_distance("seatle", true), 0);
+        assert_eq!(max_edit_distance("tchaikovsky", true), 1);
+    }
+
+    #[test]
+    fn test_split_near() {
+        let split = |query: &str| split_near(query, &query_languages(query));
+        assert_eq!(
+            split("Cafe near Pike Place"),
+            Some(NearQuery {
+                subject: "cafe".to_string(),
+                anchor: "pike place".to_string(),
+            })
+        );
+        assert_eq!(
+            split("hotel nahe bahnhof").map(|near| near.anchor),
+            Some("bahnhof".to_string())
+        );
+        assert_eq!(split("cafe near me"), None);
+        assert_eq!(split("bed in box"), None);
+        // Near phrases in languages the query isn't written in.
+        assert_eq!(split("hotel om nom"), None);
+        assert_eq!(split("bar her majesty"), None);
+        assert_eq!(split("cafe tut street"), None);
+        assert_eq!(split("obok cafe bar"), None);
+        assert_eq!(split_near("cafe nah bar", &[Language::English]), None);
+        assert_eq!(split_near("pizza ad roma", &[Language::English]), None);
+    }
+
+    #[test]
+    fn test_is_stopword() {
+        assert!(is_stopword("the", &[Language::English]));
+        assert!(!is_stopword("cafe", &[Language::English]));
+        assert!(is_stopword("die", &[Language::German]));
+        assert!(!is_stopword("die", &[Language::English]));
+        assert!(!is_stopword("the", &[]));
+    }
 }
@@ -1,149 +1,326 @@
-use std::{collections::HashMap, error::Error};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    error::Error,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
 
 use lingua::Language;
 use regex::Regex;
 
+use crate::dictionaries::{
+    dictionary, language_code, numbers, DictionaryCategory, LEVEL_CATEGORIES, NAME_CATEGORIES,
+    ROAD_CATEGORIES, TOPONYM_CATEGORIES, UNIT_CATEGORIES,
+};
+
 lazy_static! {
     static ref ASCII_WHITESPACE_RE: Regex = Regex::new(r"[ \t\r\n]+").unwrap();
 }
 
-lazy_static! {
-    static ref EN_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/en/street_types.txt")).unwrap();
-    static ref CA_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/ca/street_types.txt")).unwrap();
-    static ref ES_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/es/street_types.txt")).unwrap();
-    static ref AR_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/ar/street_types.txt")).unwrap();
-    static ref FR_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/fr/street_types.txt")).unwrap();
-    static ref DE_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/de/street_types.txt")).unwrap();
-    static ref IT_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/it/street_types.txt")).unwrap();
-    static ref PT_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/pt/street_types.txt")).unwrap();
-    static ref RU_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/ru/street_types.txt")).unwrap();
-    static ref ZH_STREET_TYPES: SubstitutionDict =
-        SubstitutionDict::from_str(include_str!("../dictionaries/zh/street_types.txt")).unwrap();
-    static ref EMPTY_SUBS: SubstitutionDict = SubstitutionDict::empty();
-}
-
-#[derive(Debug, Hash, PartialEq, Eq, Clone)]
-pub(super) struct SubstitutionDict {
-    subs: Vec<(String, Vec<String>)>,
+/// Default upper bound on the number of permutations generated from a single string.
+pub const DEFAULT_MAX_PERMUTATIONS: usize = 32;
+
+/// Longest number phrase that's recognized, e.g. "quatre vingt dix".
+const MAX_NUMBER_WORDS: usize = 4;
+
+static MAX_PERMUTATIONS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_PERMUTATIONS);
+static PERMUTATIONS_PRODUCED: AtomicU64 = AtomicU64::new(0);
+static PERMUTATIONS_TRUNCATED: AtomicU64 = AtomicU64::new(0);
+
+/// Set the upper bound on the number of permutations generated from a single string, for the
+/// rest of the process. A limit of 0 is treated as 1, since the string itself is always kept.
+pub fn set_max_permutations(limit: usize) {
+    MAX_PERMUTATIONS.store(limit.max(1), Ordering::Relaxed);
+}
+
+pub fn max_permutations() -> usize {
+    MAX_PERMUTATIONS.load(Ordering::Relaxed)
+}
+
+/// Counters for the permutations generated so far in this process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PermutationStats {
+    /// Total number of permutations produced.
+    pub produced: u64,
+    /// Number of strings whose permutations were cut off at the limit.
+    pub truncated: u64,
+}
+
+pub fn permutation_stats() -> PermutationStats {
+    PermutationStats {
+        produced: PERMUTATIONS_PRODUCED.load(Ordering::Relaxed),
+        truncated: PERMUTATIONS_TRUNCATED.load(Ordering::Relaxed),
+    }
+}
+
+/// A set of equivalent phrases, e.g. "avenue" and "ave", parsed from libpostal-style
+/// dictionaries.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SubstitutionDict {
+    subs: HashMap<String, Vec<String>>,
+    canonical: HashMap<String, String>,
 }
 
 impl SubstitutionDict {
-    fn empty() -> Self {
-        Self { subs: vec![] }
+    pub fn empty() -> Self {
+        Self {
+            subs: HashMap::new(),
+            canonical: HashMap::new(),
+        }
     }
 
-    pub(super) fn from_str(contents: &str) -> Result<Self, Box<dyn Error>> {
-        let mut subs: HashMap<String, Vec<String>> = HashMap::new();
+    /// Parse a libpostal dictionary, where each line is a pipe-separated list of equivalent
+    /// phrases. Phrases are sanitized the same way as the text they're applied to.
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn Error>> {
+        let mut dict = Self::empty();
         for line in contents.lines() {
-            let components: Vec<_> = line.split('|').collect();
-            for component in &components {
-                if let Some(existing_subs) = subs.get_mut(*component) {
-                    for component_to_add in &components {
-                        if !existing_subs.contains(&component_to_add.to_string()) {
-                            existing_subs.push(component_to_add.to_string());
-                        }
-                    }
-                } else {
-                    subs.insert(
-                        component.to_string(),
-                        components.iter().map(|s| s.to_string()).collect(),
-                    );
+            let components: Vec<_> = line
+                .split('|')
+                .map(|component| sanitize(component).trim().to_string())
+                .filter(|component| !component.is_empty())
+                .collect();
+            dict.add_line(&components);
+        }
+        Ok(dict)
+    }
+
+    fn add_line(&mut self, components: &[String]) {
+        let Some(canonical) = components.first() else {
+            return;
+        };
+        for component in components {
+            self.canonical
+                .entry(component.clone())
+                .or_insert_with(|| canonical.clone());
+            let existing_subs = self.subs.entry(component.clone()).or_default();
+            for component_to_add in components {
+                if !existing_subs.contains(component_to_add) {
+                    existing_subs.push(component_to_add.clone());
                 }
             }
         }
-        Ok(Self {
-            subs: subs.into_iter().collect(),
-        })
     }
 
+    /// Add all of the substitutions from another dictionary to this one.
+    pub fn extend(&mut self, other: &Self) {
+        for (key, subs) in &other.subs {
+            let existing_subs = self.subs.entry(key.clone()).or_default();
+            for sub in subs {
+                if !existing_subs.contains(sub) {
+                    existing_subs.push(sub.clone());
+                }
+            }
+        }
+        for (phrase, canonical) in &other.canonical {
+            self.canonical
+                .entry(phrase.clone())
+                .or_insert_with(|| canonical.clone());
+        }
+    }
+
+    /// The canonical form of a phrase, which is the first entry on its dictionary line.
+    pub fn canonical(&self, phrase: &str) -> Option<&str> {
+        self.canonical.get(phrase).map(String::as_str)
+    }
+
+    /// The token followed by its substitutions, most probable first. Dictionary lines list the
+    /// canonical phrase first and its abbreviations from most to least common, so file order is
+    /// used as the ranking.
     pub fn substitute(&self, token: &str) -> Vec<String> {
         let mut substitutions = vec![token.to_string()];
-        for (key, subs) in &self.subs {
-            if key == token {
-                substitutions.extend(subs.clone());
-            }
+        if let Some(subs) = self.subs.get(token) {
+            substitutions.extend(subs.iter().filter(|sub| *sub != token).cloned());
         }
         substitutions
     }
 }
 
-fn sanitize(field: &str) -> String {
+pub(crate) fn sanitize(field: &str) -> String {
     ASCII_WHITESPACE_RE
         .replace_all(&deunicode::deunicode(field).to_lowercase(), " ")
         .to_string()
 }
 
+/// Rewrite cardinal and ordinal numbers written as words into digits, e.g. "fifth avenue" into
+/// "5th avenue" or "calle diez" into "calle 10", so both spellings index and match the same way.
+/// Longer phrases win, so "twenty first" becomes "21st" rather than "20 1st".
+pub fn normalize_numbers(text: &str, language_code: &str) -> String {
+    let dict = numbers(language_code);
+    let text = sanitize(text);
+    let tokens: Vec<&str> = text.split_ascii_whitespace().collect();
+    let mut normalized = Vec::new();
+    let mut start = 0;
+    while start < tokens.len() {
+        let longest = (1..=MAX_NUMBER_WORDS.min(tokens.len() - start))
+            .rev()
+            .find_map(|len| {
+                let phrase = tokens[start..start + len].join(" ");
+                dict.canonical(&phrase)
+                    .map(|canonical| (len, canonical.to_string()))
+            });
+        if let Some((len, canonical)) = longest {
+            normalized.push(canonical);
+            start += len;
+        } else {
+            normalized.push(tokens[start].to_string());
+            start += 1;
+        }
+    }
+    normalized.join(" ")
+}
+
+/// Generate up to `limit` permutations of a token sequence, in order of how probable they are.
+///
+/// Each permutation is scored by the sum of the ranks of the substitutions it uses, where the
+/// original token has rank 0, and permutations are produced best-first. This means the
+/// original string always comes first, followed by those that change a single token to its most
+/// likely alternative, and so on. Returns the permutations and whether any were left out.
 pub(super) fn apply_subs(
-    prefix: &[String],
-    remaining: &[String],
+    tokens: &[String],
     dict: &SubstitutionDict,
-) -> Result<Vec<String>, Box<dyn Error>> {
-    if remaining.is_empty() {
-        return Ok(vec![prefix.join(" ")]);
-    }
+    limit: usize,
+) -> (Vec<String>, bool) {
+    let options: Vec<Vec<String>> = tokens.iter().map(|token| dict.substitute(token)).collect();
 
-    let mut permutations = vec![];
+    let mut permutations = Vec::new();
+    let mut queue = BinaryHeap::new();
+    let mut seen = HashSet::new();
+    let start = vec![0; options.len()];
+    seen.insert(start.clone());
+    queue.push(Reverse((0, start)));
 
-    for sub in dict.substitute(&remaining[0]) {
-        let mut prefix = prefix.to_vec();
-        prefix.push(sub);
-        let mut remaining = remaining.to_vec();
-        remaining.remove(0);
-        permutations.extend(apply_subs(&prefix, &remaining, dict)?);
+    while let Some(Reverse((score, choice))) = queue.pop() {
+        if permutations.len() >= limit {
+            return (permutations, true);
+        }
+        permutations.push(
+            choice
+                .iter()
+                .zip(&options)
+                .map(|(index, subs)| subs[*index].as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        for position in 0..choice.len() {
+            if choice[position] + 1 < options[position].len() {
+                let mut next = choice.clone();
+                next[position] += 1;
+                if seen.insert(next.clone()) {
+                    queue.push(Reverse((score + 1, next)));
+                }
+            }
+        }
     }
 
-    Ok(permutations)
+    (permutations, false)
 }
 
-pub fn permute_road(road: &str, language: &Language) -> Result<Vec<String>, Box<dyn Error>> {
-    let sub_dict: &SubstitutionDict = match language {
-        Language::English => &EN_STREET_TYPES,
-        Language::Arabic => &AR_STREET_TYPES,
-        Language::Spanish => &ES_STREET_TYPES,
-        Language::French => &FR_STREET_TYPES,
-        Language::German => &DE_STREET_TYPES,
-        Language::Italian => &IT_STREET_TYPES,
-        Language::Portuguese => &PT_STREET_TYPES,
-        Language::Russian => &RU_STREET_TYPES,
-        Language::Chinese => &ZH_STREET_TYPES,
-        Language::Catalan => &CA_STREET_TYPES,
-        _ => &EMPTY_SUBS,
-    };
-    let road_tokens: Vec<String> = sanitize(road)
+/// Permute a string using the dictionaries of the given categories for a language, producing at
+/// most [`max_permutations`] strings.
+pub fn permute(
+    text: &str,
+    language: &Language,
+    categories: &[DictionaryCategory],
+) -> Result<Vec<String>, Box<dyn Error>> {
+    permute_within(text, language, categories, max_permutations())
+}
+
+/// Like [`permute`], but producing at most `limit` strings, for callers that split the budget of
+/// [`max_permutations`] between several permutations of the same field.
+pub fn permute_within(
+    text: &str,
+    language: &Language,
+    categories: &[DictionaryCategory],
+    limit: usize,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let sub_dict = dictionary(&language_code(language), categories);
+    let tokens: Vec<String> = sanitize(text)
         .split_ascii_whitespace()
         .map(|s| s.to_string())
         .collect();
-    apply_subs(&[], &road_tokens, sub_dict)
+    let (permutations, truncated) = apply_subs(&tokens, &sub_dict, limit.max(1));
+    PERMUTATIONS_PRODUCED.fetch_add(permutations.len() as u64, Ordering::Relaxed);
+    if truncated {
+        PERMUTATIONS_TRUNCATED.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(permutations)
+}
+
+pub fn permute_road(road: &str, language: &Language) -> Result<Vec<String>, Box<dyn Error>> {
+    permute(road, language, ROAD_CATEGORIES)
+}
+
+pub fn permute_unit(unit: &str, language: &Language) -> Result<Vec<String>, Box<dyn Error>> {
+    permute(unit, language, UNIT_CATEGORIES)
+}
+
+pub fn permute_level(level: &str, language: &Language) -> Result<Vec<String>, Box<dyn Error>> {
+    permute(level, language, LEVEL_CATEGORIES)
+}
+
+pub fn permute_name(name: &str, language: &Language) -> Result<Vec<String>, Box<dyn Error>> {
+    permute(name, language, NAME_CATEGORIES)
+}
+
+pub fn permute_toponym(toponym: &str, language: &Language) -> Result<Vec<String>, Box<dyn Error>> {
+    permute(toponym, language, TOPONYM_CATEGORIES)
 }
 
 #[cfg(test)]
 mod test {
     use lingua::Language;
 
-    use crate::substitutions::permute_road;
+    use crate::{
+        dictionaries::{dictionary, ROAD_CATEGORIES},
+        substitutions::{
+            apply_subs, normalize_numbers, permute_road, permute_unit, DEFAULT_MAX_PERMUTATIONS,
+        },
+    };
 
     #[test]
     fn test_permute_road() {
         let road = "fremont ave n";
         let permutations = permute_road(road, &Language::English).unwrap();
-        dbg!(permutations.clone());
-        assert_eq!(permutations.len(), 3);
+        assert_eq!(permutations[0], road);
+        assert!(permutations.contains(&"fremont avenue north".to_string()));
+        assert!(permutations.len() <= DEFAULT_MAX_PERMUTATIONS);
     }
 
     #[test]
     fn test_permute_road_cat() {
         let road = "carrer de villarroel";
         let permutations = permute_road(road, &Language::Catalan).unwrap();
-        dbg!(permutations.clone());
-        assert_eq!(permutations.len(), 3);
+        assert_eq!(permutations[0], road);
+        assert!(permutations.contains(&"carr de villarroel".to_string()));
+    }
+
+    #[test]
+    fn test_apply_subs_bounded() {
+        let dict = dictionary("en", ROAD_CATEGORIES);
+        let tokens = vec!["fremont".to_string(), "ave".to_string(), "n".to_string()];
+        let (all, truncated) = apply_subs(&tokens, &dict, usize::MAX);
+        assert!(!truncated);
+        let (bounded, truncated) = apply_subs(&tokens, &dict, 3);
+        assert!(truncated);
+        assert_eq!(bounded, all[..3]);
+        assert_eq!(bounded[0], "fremont ave n");
+    }
+
+    #[test]
+    fn test_normalize_numbers() {
+        assert_eq!(normalize_numbers("Fifth Avenue", "en"), "5th avenue");
+        assert_eq!(normalize_numbers("twenty-first st", "en"), "21st st");
+        assert_eq!(normalize_numbers("Calle Diez", "es"), "calle 10");
+        assert_eq!(normalize_numbers("Calle 1ª", "es"), "calle 1a");
+        assert_eq!(
+            normalize_numbers("Rue du Quatre Septembre", "fr"),
+            "rue du 4 septembre"
+        );
+    }
+
+    #[test]
+    fn test_permute_unit() {
+        let permutations = permute_unit("Apt 4", &Language::English).unwrap();
+        assert!(permutations.contains(&"apartment 4".to_string()));
     }
 }
@@ -0,0 +1,122 @@
+//! Opening a remote index again, the way the service reloads it, shouldn't leave anything behind.
+//! This counts the threads in the process, so it's the only test in its binary.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+};
+
+use airmail::{
+    index::AirmailIndex,
+    poi::{SchemafiedPoi, ToIndexPoi},
+};
+use lingua::Language;
+use tokio::task::spawn_blocking;
+
+/// Serve the files in `dir` over HTTP, with just enough of the protocol for a remote index.
+fn serve(dir: PathBuf) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            let request = lines.next().unwrap().unwrap();
+            let mut range = None;
+            for line in lines
+                .map(Result::unwrap)
+                .take_while(|line| !line.is_empty())
+            {
+                if let Some(bytes) = line.to_lowercase().strip_prefix("range: bytes=") {
+                    let (start, end) = bytes.split_once('-').unwrap();
+                    range = Some((start.parse().unwrap(), end.parse::<usize>().unwrap() + 1));
+                }
+            }
+            let (method, path) = request.split_once(' ').unwrap();
+            let path = path.split(' ').next().unwrap().trim_start_matches('/');
+            let Ok(body) = std::fs::read(dir.join(path)) else {
+                write!(
+                    stream,
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+                )
+                .unwrap();
+                continue;
+            };
+            let (status, body) = match range {
+                Some((start, end)) => (
+                    "206 Partial Content",
+                    body[start..end.min(body.len())].to_vec(),
+                ),
+                None => ("200 OK", body),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            )
+            .unwrap();
+            if method == "GET" {
+                stream.write_all(&body).unwrap();
+            }
+        }
+    });
+    url
+}
+
+fn thread_count() -> usize {
+    std::fs::read_dir("/proc/self/task").unwrap().count()
+}
+
+/// Open the index at `url` and search it, like the service does when it reloads an index.
+async fn reload(url: &str) {
+    let location = url.to_string();
+    let index = spawn_blocking(move || AirmailIndex::open(&location))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(index.num_docs().await.unwrap(), 1);
+    let results = index
+        .search("harbor cafe", false, None, None, &[])
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn test_reload_remote_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut index = AirmailIndex::create(dir.path()).unwrap();
+    let mut writer = index.writer().unwrap();
+    let tags = vec![("name".to_string(), "Harbor Cafe".to_string())];
+    let mut poi = ToIndexPoi::new(
+        vec!["Harbor Cafe".to_string()],
+        None,
+        None,
+        None,
+        47.6,
+        -122.3,
+        tags,
+    )
+    .unwrap();
+    poi.languages = vec![Language::English];
+    writer.add_poi(SchemafiedPoi::from(poi), "test").unwrap();
+    writer.commit().unwrap();
+    let url = serve(dir.path().to_path_buf());
+
+    // With one blocking thread, every request is made from the same thread, and the threads
+    // behind its HTTP clients are only started once.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .max_blocking_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        reload(&url).await;
+        let threads = thread_count();
+        reload(&url).await;
+        reload(&url).await;
+        assert_eq!(thread_count(), threads);
+    });
+}
@@ -0,0 +1,457 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+use airmail::{
+    bbox::{parse_bbox, Bbox},
+    index::AirmailIndex,
+    poi::AirmailPoi,
+};
+use anyhow::{anyhow, Result};
+use clap::{Parser, ValueEnum};
+use env_logger::Env;
+use futures_util::{stream, StreamExt};
+use log::{info, warn};
+use serde_json::{json, Value};
+
+/// Geocode every row of a CSV or NDJSON file against an index, without a server.
+#[derive(Debug, Parser)]
+struct Args {
+    /// The index to search, a local path or a URL.
+    #[clap(long, short)]
+    index: String,
+    /// The file of addresses to geocode.
+    #[clap(long)]
+    input: PathBuf,
+    /// Where to write the geocoded rows. Defaults to stdout.
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+    /// The input's format. Defaults to NDJSON for `.ndjson` and `.jsonl` files, otherwise CSV.
+    #[clap(long, value_enum)]
+    format: Option<Format>,
+    /// The columns (or NDJSON keys) making up the address, in order, e.g.
+    /// `--columns street,city,postcode`. Their values are joined into the query.
+    #[clap(long, short, value_delimiter = ',', required = true)]
+    columns: Vec<String>,
+    /// Only return results within `min_lng,min_lat,max_lng,max_lat`.
+    #[clap(long, short)]
+    bbox: Option<String>,
+    /// Accept results that don't match every token of the address.
+    #[clap(long)]
+    leniency: bool,
+    /// How many rows are searched at once.
+    #[clap(long, default_value_t = 8)]
+    parallelism: usize,
+    /// Prepended to the names of the result columns, e.g. `geocode_` for `geocode_lat`, for
+    /// inputs that already have columns named like them.
+    #[clap(long, default_value = "")]
+    result_prefix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Csv,
+    Ndjson,
+}
+
+/// The columns added to each row, see `--result-prefix`.
+const RESULT_COLUMNS: [&str; 6] = ["lat", "lng", "confidence", "match_type", "name", "s2cell"];
+
+/// Rows are identified by this column, which is added with each row's number if the input
+/// doesn't have one.
+const ID_COLUMN: &str = "id";
+
+/// A short name for a result: its name if it has one, otherwise its address.
+fn display_name(poi: &AirmailPoi) -> String {
+    let tag = |key: &str| {
+        poi.tags
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+    if let Some(name) = tag("name") {
+        return name.to_string();
+    }
+    [tag("addr:housenumber"), tag("addr:street")]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The values of the result columns for the best result, or empty ones if there wasn't one.
+fn result_values(result: Option<&(AirmailPoi, f32)>) -> [String; 6] {
+    match result {
+        Some((poi, _)) => [
+            poi.lat.to_string(),
+            poi.lng.to_string(),
+            format!("{:.3}", poi.confidence),
+            json!(poi.match_type)
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            display_name(poi),
+            poi.s2cell.to_string(),
+        ],
+        None => Default::default(),
+    }
+}
+
+/// Like [`result_values`], but typed for NDJSON, with nulls if there wasn't a result.
+fn result_json(result: Option<&(AirmailPoi, f32)>) -> [Value; 6] {
+    match result {
+        Some((poi, _)) => [
+            json!(poi.lat),
+            json!(poi.lng),
+            json!(poi.confidence),
+            json!(poi.match_type),
+            json!(display_name(poi)),
+            json!(poi.s2cell.to_string()),
+        ],
+        None => Default::default(),
+    }
+}
+
+/// Geocodes the rows of an input as they're read, writing each out once it's searched.
+struct Geocoder<'a> {
+    index: &'a AirmailIndex,
+    /// The columns making up the address, in order.
+    columns: &'a [String],
+    leniency: bool,
+    bbox: Option<Bbox>,
+    /// How many rows are searched at once.
+    parallelism: usize,
+    /// The names of the result columns, in the order of [`RESULT_COLUMNS`].
+    result_columns: [String; 6],
+}
+
+impl Geocoder<'_> {
+    async fn geocode(&self, query: &str) -> Option<(AirmailPoi, f32)> {
+        if query.trim().is_empty() {
+            return None;
+        }
+        match self
+            .index
+            .search(query, self.leniency, None, self.bbox, &[])
+            .await
+        {
+            Ok(results) => results.into_iter().max_by(|(_, a), (_, b)| a.total_cmp(b)),
+            Err(err) => {
+                warn!("Failed to geocode {:?}: {:#}", query, err);
+                None
+            }
+        }
+    }
+
+    /// An error for an input that already has a column named like a result column.
+    fn collision(&self, column: &str) -> anyhow::Error {
+        anyhow!(
+            "The input already has a {} column, use --result-prefix to name the result columns \
+             differently",
+            column
+        )
+    }
+
+    /// Geocode a CSV file, returning how many rows were found and how many there were in all.
+    /// Rows are searched concurrently, but written in the order they were read.
+    async fn csv(&self, input: impl Read, output: impl Write) -> Result<(usize, usize)> {
+        let mut reader = csv::Reader::from_reader(input);
+        let headers = reader.headers()?.clone();
+        if let Some(column) = self
+            .result_columns
+            .iter()
+            .find(|column| headers.iter().any(|header| header == *column))
+        {
+            return Err(self.collision(column));
+        }
+        let indices = self
+            .columns
+            .iter()
+            .map(|column| {
+                headers
+                    .iter()
+                    .position(|header| header == column)
+                    .ok_or_else(|| anyhow!("No column named {} in the input", column))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let rows = reader.into_records().map(|record| {
+            let record = record?;
+            let query = indices
+                .iter()
+                .filter_map(|index| record.get(*index))
+                .filter(|value| !value.trim().is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            Ok::<_, anyhow::Error>((query, record))
+        });
+
+        let mut writer = csv::Writer::from_writer(output);
+        let add_id = !headers.iter().any(|header| header == ID_COLUMN);
+        writer.write_record(
+            headers
+                .iter()
+                .chain(self.result_columns.iter().map(String::as_str))
+                .chain(add_id.then_some(ID_COLUMN)),
+        )?;
+        let mut results = stream::iter(rows)
+            .map(|row| async move {
+                let (query, record) = row?;
+                Ok::<_, anyhow::Error>((record, self.geocode(&query).await))
+            })
+            .buffered(self.parallelism);
+        let (mut found, mut total) = (0, 0);
+        while let Some(row) = results.next().await {
+            let (record, result) = row?;
+            total += 1;
+            found += usize::from(result.is_some());
+            let values = result_values(result.as_ref());
+            let id = add_id.then(|| total.to_string());
+            writer.write_record(
+                record
+                    .iter()
+                    .chain(values.iter().map(String::as_str))
+                    .chain(id.as_deref()),
+            )?;
+        }
+        writer.flush()?;
+        Ok((found, total))
+    }
+
+    /// Like [`Geocoder::csv`], but for NDJSON, with one JSON object per line.
+    async fn ndjson(&self, input: impl Read, mut output: impl Write) -> Result<(usize, usize)> {
+        let rows = BufReader::new(input)
+            .lines()
+            .enumerate()
+            .filter_map(|(number, line)| {
+                let row = || {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        return Ok(None);
+                    }
+                    let Value::Object(row) = serde_json::from_str(&line)? else {
+                        return Err(anyhow!("Line {} is not a JSON object", number + 1));
+                    };
+                    if let Some(column) = self
+                        .result_columns
+                        .iter()
+                        .find(|column| row.contains_key(*column))
+                    {
+                        return Err(self.collision(column));
+                    }
+                    let query = self
+                        .columns
+                        .iter()
+                        .filter_map(|column| match row.get(column) {
+                            Some(Value::String(value)) => Some(value.clone()),
+                            Some(Value::Number(value)) => Some(value.to_string()),
+                            _ => None,
+                        })
+                        .filter(|value| !value.trim().is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    Ok(Some((query, row)))
+                };
+                row().transpose()
+            });
+
+        let mut results = stream::iter(rows)
+            .map(|row| async move {
+                let (query, row) = row?;
+                Ok::<_, anyhow::Error>((row, self.geocode(&query).await))
+            })
+            .buffered(self.parallelism);
+        let (mut found, mut total) = (0, 0);
+        while let Some(row) = results.next().await {
+            let (mut row, result) = row?;
+            total += 1;
+            found += usize::from(result.is_some());
+            let values = result_json(result.as_ref());
+            for (column, value) in self.result_columns.iter().zip(values) {
+                row.insert(column.clone(), value);
+            }
+            row.entry(ID_COLUMN).or_insert_with(|| json!(total));
+            writeln!(output, "{}", Value::Object(row))?;
+        }
+        output.flush()?;
+        Ok((found, total))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+    let index = AirmailIndex::open(&args.index)?;
+    let format = args.format.unwrap_or_else(|| {
+        match args
+            .input
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("ndjson" | "jsonl") => Format::Ndjson,
+            _ => Format::Csv,
+        }
+    });
+    let geocoder = Geocoder {
+        index: &index,
+        columns: &args.columns,
+        leniency: args.leniency,
+        bbox: args.bbox.as_deref().map(parse_bbox).transpose()?,
+        parallelism: args.parallelism.max(1),
+        result_columns: RESULT_COLUMNS.map(|column| format!("{}{}", args.result_prefix, column)),
+    };
+
+    let input = File::open(&args.input)?;
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    let start = std::time::Instant::now();
+    let (found, total) = match format {
+        Format::Csv => geocoder.csv(input, output).await?,
+        Format::Ndjson => geocoder.ndjson(input, output).await?,
+    };
+    info!(
+        "Geocoded {} of {} rows in {:?}",
+        found,
+        total,
+        start.elapsed()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use airmail::{
+        index::AirmailIndex,
+        poi::{SchemafiedPoi, ToIndexPoi},
+    };
+    use lingua::Language;
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+
+    use super::{Geocoder, RESULT_COLUMNS};
+
+    /// An index of a single cafe.
+    fn cafe_index() -> (TempDir, AirmailIndex) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = AirmailIndex::create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        let tags = vec![("name".to_string(), "Harbor Cafe".to_string())];
+        let mut poi = ToIndexPoi::new(
+            vec!["Harbor Cafe".to_string()],
+            None,
+            None,
+            None,
+            47.6,
+            -122.3,
+            tags,
+        )
+        .unwrap();
+        poi.languages = vec![Language::English];
+        writer.add_poi(SchemafiedPoi::from(poi), "test").unwrap();
+        writer.commit().unwrap();
+        (dir, index)
+    }
+
+    fn geocoder<'a>(index: &'a AirmailIndex, columns: &'a [String], prefix: &str) -> Geocoder<'a> {
+        Geocoder {
+            index,
+            columns,
+            leniency: false,
+            bbox: None,
+            parallelism: 2,
+            result_columns: RESULT_COLUMNS.map(|column| format!("{}{}", prefix, column)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_csv() {
+        let (_dir, index) = cafe_index();
+        let columns = vec!["place".to_string()];
+        let geocoder = geocoder(&index, &columns, "");
+        let input = "place,note\nHarbor Cafe,a\nQwxyz,b\n";
+        let mut output = Vec::new();
+        let counts = geocoder.csv(input.as_bytes(), &mut output).await.unwrap();
+        assert_eq!(counts, (1, 2));
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "place,note,lat,lng,confidence,match_type,name,s2cell,id"
+        );
+        let row = lines[1].split(',').collect::<Vec<_>>();
+        assert_eq!(row[..2], ["Harbor Cafe", "a"]);
+        assert!((row[2].parse::<f64>().unwrap() - 47.6).abs() < 1e-6);
+        assert!((row[3].parse::<f64>().unwrap() + 122.3).abs() < 1e-6);
+        assert_eq!(row[6..], ["Harbor Cafe", row[7], "1"]);
+        // Rows without a match keep their place, with empty results.
+        assert_eq!(lines[2], "Qwxyz,b,,,,,,,2");
+
+        // Ids already in the input are passed through.
+        let input = "id,place\nx7,Harbor Cafe\n";
+        let mut output = Vec::new();
+        geocoder.csv(input.as_bytes(), &mut output).await.unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "id,place,lat,lng,confidence,match_type,name,s2cell"
+        );
+        assert!(lines[1].starts_with("x7,Harbor Cafe,47.6"));
+    }
+
+    #[tokio::test]
+    async fn test_ndjson() {
+        let (_dir, index) = cafe_index();
+        let columns = vec!["place".to_string()];
+        let geocoder = geocoder(&index, &columns, "");
+        let input = "{\"id\":\"x7\",\"place\":\"Harbor Cafe\"}\n\n{\"place\":\"Qwxyz\"}\n";
+        let mut output = Vec::new();
+        let counts = geocoder
+            .ndjson(input.as_bytes(), &mut output)
+            .await
+            .unwrap();
+        assert_eq!(counts, (1, 2));
+        let rows = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["id"], json!("x7"));
+        assert_eq!(rows[0]["place"], json!("Harbor Cafe"));
+        assert!((rows[0]["lat"].as_f64().unwrap() - 47.6).abs() < 1e-6);
+        assert_eq!(rows[0]["name"], json!("Harbor Cafe"));
+        assert_eq!(rows[1]["id"], json!(2));
+        for column in RESULT_COLUMNS {
+            assert_eq!(rows[1][column], Value::Null);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_result_column_collisions() {
+        let (_dir, index) = cafe_index();
+        let columns = vec!["place".to_string()];
+        let input = "place,name\nHarbor Cafe,cafe\n";
+        assert!(geocoder(&index, &columns, "")
+            .csv(input.as_bytes(), Vec::new())
+            .await
+            .is_err());
+        let mut output = Vec::new();
+        geocoder(&index, &columns, "geocode_")
+            .csv(input.as_bytes(), &mut output)
+            .await
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("place,name,geocode_lat,geocode_lng,"));
+
+        let input = "{\"place\":\"Harbor Cafe\",\"lat\":1}\n";
+        assert!(geocoder(&index, &columns, "")
+            .ndjson(input.as_bytes(), Vec::new())
+            .await
+            .is_err());
+    }
+}
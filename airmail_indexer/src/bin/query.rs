@@ -1,6 +1,5 @@
-use airmail::index::AirmailIndex;
+use airmail::{bbox::parse_bbox, index::AirmailIndex};
 use clap::Parser;
-use geo::Coord;
 use rustyline::DefaultEditor;
 
 #[derive(Debug, Parser)]
@@ -9,6 +8,9 @@ struct Args {
     index: String,
     #[clap(long, short)]
     bbox: Option<String>,
+    /// Print the planned query clauses, why each result matched and where the time went.
+    #[clap(long)]
+    explain: bool,
 }
 
 #[tokio::main]
@@ -17,39 +19,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let index = AirmailIndex::new(&args.index)?;
     let mut rl = DefaultEditor::new()?;
 
-    let bbox = args.bbox.map(|s| {
-        let mut parts = s.split(',');
-        let min_lng = parts
-            .next()
-            .expect("Invalid bbox format. Need: `lng1,lat1,lng2,lat2`")
-            .parse()
-            .unwrap();
-        let min_lat = parts
-            .next()
-            .expect("Invalid bbox format. Need: `lng1,lat1,lng2,lat2`")
-            .parse()
-            .unwrap();
-        let max_lng = parts
-            .next()
-            .expect("Invalid bbox format. Need: `lng1,lat1,lng2,lat2`")
-            .parse()
-            .unwrap();
-        let max_lat = parts
-            .next()
-            .expect("Invalid bbox format. Need: `lng1,lat1,lng2,lat2`")
-            .parse()
-            .unwrap();
-        geo::Rect::new(
-            Coord {
-                y: min_lat,
-                x: min_lng,
-            },
-            Coord {
-                y: max_lat,
-                x: max_lng,
-            },
-        )
-    });
+    let bbox = args.bbox.as_deref().map(parse_bbox).transpose()?;
 
     loop {
         let query = rl.readline("query: ")?;
@@ -57,11 +27,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let start = std::time::Instant::now();
         let query = query.trim().to_lowercase();
 
-        let mut results = index.search(&query, true, None, bbox, &[]).await.unwrap();
+        let (mut results, explanation) = index
+            .search_explained(&query, true, None, bbox, &[], args.explain)
+            .await
+            .unwrap();
 
+        // Results are already in score order, and the sort is stable, so they still line up
+        // with their explanations.
         results.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
-        for (poi, score) in results.iter().take(10) {
+        if args.explain {
+            println!("components: {:?}", explanation.components);
+            for clause in &explanation.clauses {
+                println!("clause: {}", serde_json::to_string(clause)?);
+            }
+        }
+        for (i, (poi, score)) in results.iter().enumerate().take(10) {
             println!("{:?} {}", poi, score);
+            if let Some(result) = explanation.results.get(i) {
+                println!("  matched: {}", result.matched_terms.join(", "));
+                println!("  importance: {}", result.importance);
+                if let Some(explanation) = &result.explanation {
+                    println!("  {}", explanation.to_pretty_json().replace('\n', "\n  "));
+                }
+            }
+        }
+        if args.explain {
+            println!("timings: {}", serde_json::to_string(&explanation.timings)?);
         }
         println!("{} results found in {:?}", results.len(), start.elapsed());
     }
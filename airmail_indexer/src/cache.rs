@@ -16,6 +16,9 @@ const TABLE_NAMES: TableDefinition<u64, &str> = TableDefinition::new("admin_name
 const TABLE_LANGS: TableDefinition<u64, &str> = TableDefinition::new("admin_langs");
 const TABLE_NODE_LOCATION: TableDefinition<i64, (f64, f64)> =
     TableDefinition::new("admin_node_location");
+const TABLE_NODE_ADDRESS: TableDefinition<i64, &str> = TableDefinition::new("node_address");
+const TABLE_MARKERS: TableDefinition<&str, ()> = TableDefinition::new("markers");
+const MARKER_NODE_ADDRESSES: &str = "node_addresses";
 pub const BUFFER_SIZE: usize = 25000;
 
 /// A cache for storing administrative area information.
@@ -35,6 +38,8 @@ impl IndexerCache {
         txn.open_table(TABLE_NAMES)?;
         txn.open_table(TABLE_LANGS)?;
         txn.open_table(TABLE_NODE_LOCATION)?;
+        txn.open_table(TABLE_NODE_ADDRESS)?;
+        txn.open_table(TABLE_MARKERS)?;
         txn.commit()?;
 
         Ok(Self {
@@ -106,6 +111,40 @@ impl IndexerCache {
         Ok(None)
     }
 
+    /// Lookup a node id in the cache and return its house number and street, if it has an address
+    pub fn query_node_address(&self, node_id: i64) -> Result<Option<(String, Option<String>)>> {
+        let txn = self.database.begin_read()?;
+        let table = txn.open_table(TABLE_NODE_ADDRESS)?;
+        if let Some(address) = table.get(node_id)? {
+            let address = address.value();
+            let mut parts = address.split('\0');
+            let house_number = parts.next().unwrap_or_default().to_string();
+            let street = parts.next().map(|s| s.to_string());
+            return Ok(Some((house_number, street)));
+        }
+        Ok(None)
+    }
+
+    /// Whether node addresses have been cached. Caches built before addresses were cached only
+    /// have node locations.
+    pub fn has_node_addresses(&self) -> Result<bool> {
+        let txn = self.database.begin_read()?;
+        let table = txn.open_table(TABLE_MARKERS)?;
+        let has_node_addresses = table.get(MARKER_NODE_ADDRESSES)?.is_some();
+        Ok(has_node_addresses)
+    }
+
+    /// Record that every node address has been cached, flushing any buffered ones first
+    pub fn set_has_node_addresses(&self) -> Result<()> {
+        self.flush()?;
+        let write = self.database.begin_write()?;
+        write
+            .open_table(TABLE_MARKERS)?
+            .insert(MARKER_NODE_ADDRESSES, ())?;
+        write.commit()?;
+        Ok(())
+    }
+
     /// Write an item to the cache, items will be written to a buffer
     /// and flushed to the database when the buffer is full.
     pub fn buffered_write_item(&self, item: WofCacheItem) -> Result<()> {
@@ -132,6 +171,7 @@ impl IndexerCache {
             let mut langs_table = write.open_table(TABLE_LANGS)?;
             let mut areas_table = write.open_table(TABLE_AREAS)?;
             let mut locations_tabls = write.open_table(TABLE_NODE_LOCATION)?;
+            let mut addresses_table = write.open_table(TABLE_NODE_ADDRESS)?;
 
             for item in buffer.drain(..) {
                 match item {
@@ -153,6 +193,13 @@ impl IndexerCache {
                     WofCacheItem::NodeLocation(node_id, location) => {
                         locations_tabls.insert(node_id, location)?;
                     }
+                    WofCacheItem::NodeAddress(node_id, house_number, street) => {
+                        let packed = match street {
+                            Some(street) => format!("{}\0{}", house_number, street),
+                            None => house_number,
+                        };
+                        addresses_table.insert(node_id, packed.as_str())?;
+                    }
                 }
             }
         }
@@ -192,4 +239,33 @@ pub enum WofCacheItem {
     Langs(u64, Vec<String>),
     Admins(u64, Vec<u64>),
     NodeLocation(i64, (f64, f64)),
+    NodeAddress(i64, String, Option<String>),
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IndexerCache, WofCacheItem};
+
+    #[test]
+    fn test_node_addresses_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.redb");
+        {
+            let cache = IndexerCache::new(&path).unwrap();
+            assert!(!cache.has_node_addresses().unwrap());
+            cache
+                .buffered_write_item(WofCacheItem::NodeAddress(1, "10".to_string(), None))
+                .unwrap();
+            cache.set_has_node_addresses().unwrap();
+            assert!(cache.has_node_addresses().unwrap());
+            assert_eq!(
+                cache.query_node_address(1).unwrap(),
+                Some(("10".to_string(), None))
+            );
+        }
+        assert!(IndexerCache::new(&path)
+            .unwrap()
+            .has_node_addresses()
+            .unwrap());
+    }
 }
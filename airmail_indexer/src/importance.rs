@@ -0,0 +1,163 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Result;
+use log::{info, warn};
+
+/// Per-object importance overrides, keyed by wikidata ID (e.g. `Q90`).
+///
+/// The rank file is a plain text file with one `wikidata_id,rank` pair per line, where rank is
+/// between 0 and 1. Lines starting with `#` are ignored. Ranks from the file take precedence
+/// over the heuristic in [`importance`] when they're higher.
+#[derive(Debug, Default, Clone)]
+pub struct ImportanceRanks {
+    ranks: HashMap<String, f64>,
+}
+
+impl ImportanceRanks {
+    /// Load a rank file from disk.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut ranks = HashMap::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((id, rank)) = line.split_once(',') else {
+                warn!("Skipping malformed importance rank line: {}", line);
+                continue;
+            };
+            match rank.trim().parse::<f64>() {
+                Ok(rank) => {
+                    ranks.insert(id.trim().to_string(), rank.clamp(0.0, 1.0));
+                }
+                Err(_) => warn!("Skipping malformed importance rank line: {}", line),
+            }
+        }
+        info!("Loaded {} importance ranks from {:?}", ranks.len(), path);
+        Ok(Self { ranks })
+    }
+
+    /// Lookup the rank for a wikidata ID.
+    pub fn rank(&self, wikidata_id: &str) -> Option<f64> {
+        self.ranks.get(wikidata_id).copied()
+    }
+}
+
+/// Importance of a `place=*` value. Larger settlements are more likely to be what someone is
+/// looking for when a name is ambiguous.
+fn place_importance(place: &str) -> f64 {
+    match place {
+        "continent" => 1.0,
+        "country" => 0.95,
+        "state" | "province" => 0.85,
+        "region" => 0.75,
+        "city" => 0.8,
+        "county" => 0.6,
+        "town" => 0.6,
+        "island" => 0.5,
+        "borough" | "suburb" => 0.4,
+        "village" => 0.4,
+        "quarter" => 0.3,
+        "hamlet" | "neighbourhood" => 0.25,
+        "locality" | "isolated_dwelling" | "farm" => 0.15,
+        _ => 0.1,
+    }
+}
+
+/// Importance of a place's `population`, so that a large village can outrank a small town. Values
+/// like `12,345` are accepted, anything else that isn't a whole number is ignored.
+fn population_importance(population: &str) -> Option<f64> {
+    let population: f64 = population.replace([',', ' '], "").parse::<u64>().ok()? as f64;
+    Some((population.max(1.0).log10() / 8.0).clamp(0.0, 0.9))
+}
+
+/// Compute the importance of a POI from its OSM tags, between 0 and 1.
+///
+/// This is a heuristic combining the type of place, its population, whether it has
+/// wikidata/wikipedia links, and how richly tagged it is. If `ranks` is provided and contains
+/// the POI's wikidata ID, the higher of the two values is used.
+pub fn importance(tags: &[(String, String)], ranks: Option<&ImportanceRanks>) -> f64 {
+    let mut base: f64 = 0.0;
+    let mut bonus = 0.0;
+    let mut wikidata_id = None;
+
+    for (key, value) in tags {
+        match key.as_str() {
+            "place" => base = base.max(place_importance(value)),
+            "population" => base = base.max(population_importance(value).unwrap_or_default()),
+            "wikidata" => {
+                bonus += 0.2;
+                wikidata_id = Some(value.as_str());
+            }
+            "wikipedia" => bonus += 0.1,
+            _ => {}
+        }
+    }
+
+    // Well-known places tend to be well-mapped.
+    #[allow(clippy::cast_precision_loss)]
+    let richness = tags.len().min(30) as f64 / 30.0 * 0.1;
+    let heuristic = (base + bonus + richness).clamp(0.0, 1.0);
+
+    let rank = ranks
+        .zip(wikidata_id)
+        .and_then(|(ranks, id)| ranks.rank(id))
+        .unwrap_or_default();
+    heuristic.max(rank)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{importance, ImportanceRanks};
+
+    fn tags(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+        tags.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_place_importance() {
+        let city = importance(&tags(&[("place", "city"), ("name", "Paris")]), None);
+        let village = importance(&tags(&[("place", "village"), ("name", "Paris")]), None);
+        let bus_stop = importance(&tags(&[("highway", "bus_stop"), ("name", "Paris")]), None);
+        assert!(city > village);
+        assert!(village > bus_stop);
+
+        let big_village = importance(
+            &tags(&[
+                ("place", "village"),
+                ("name", "Paris"),
+                ("population", "120,000"),
+            ]),
+            None,
+        );
+        assert!(big_village > village);
+        assert!(importance(&tags(&[("place", "village"), ("population", "lots")]), None) > 0.0);
+    }
+
+    #[test]
+    fn test_wikidata_importance() {
+        let cafe = tags(&[("amenity", "cafe"), ("name", "Cafe Paris")]);
+        let mut linked = cafe.clone();
+        linked.push(("wikidata".to_string(), "Q1".to_string()));
+        linked.push(("wikipedia".to_string(), "en:Cafe Paris".to_string()));
+        // The two extra tags also make it slightly richer.
+        let bonus = importance(&linked, None) - importance(&cafe, None);
+        assert!((bonus - (0.3 + 2.0 / 30.0 * 0.1)).abs() < 1e-9);
+
+        let ranks = ImportanceRanks {
+            ranks: HashMap::from([("Q1".to_string(), 0.9), ("Q2".to_string(), 0.01)]),
+        };
+        assert_eq!(importance(&linked, Some(&ranks)), 0.9);
+        let mut low_rank = cafe.clone();
+        low_rank.push(("wikidata".to_string(), "Q2".to_string()));
+        assert_eq!(
+            importance(&low_rank, Some(&ranks)),
+            importance(&low_rank, None)
+        );
+    }
+}
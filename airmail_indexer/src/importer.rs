@@ -1,6 +1,8 @@
 use airmail::{
+    dictionaries::load_dictionary_dir,
     index::AirmailIndex,
     poi::{SchemafiedPoi, ToIndexPoi},
+    substitutions::{permutation_stats, set_max_permutations},
 };
 use anyhow::Result;
 use crossbeam::channel::{Receiver, Sender};
@@ -19,8 +21,10 @@ use tokio::{
 
 use crate::{
     cache::{IndexerCache, WofCacheItem},
+    importance::{importance, ImportanceRanks},
     pip_tree::PipTree,
     query_pip,
+    wikidata::Wikidata,
     wof::{ConcisePipResponse, WhosOnFirst},
 };
 
@@ -29,6 +33,10 @@ pub struct ImporterBuilder {
     admin_cache_path: Option<PathBuf>,
     wof_db_path: PathBuf,
     pip_tree_path: Option<PathBuf>,
+    importance_ranks_path: Option<PathBuf>,
+    wikidata_path: Option<PathBuf>,
+    max_permutations: Option<usize>,
+    dictionary_dirs: Vec<PathBuf>,
 }
 
 impl ImporterBuilder {
@@ -41,6 +49,10 @@ impl ImporterBuilder {
             admin_cache_path: None,
             wof_db_path: wof_db_path.to_path_buf(),
             pip_tree_path: None,
+            importance_ranks_path: None,
+            wikidata_path: None,
+            max_permutations: None,
+            dictionary_dirs: Vec::new(),
         })
     }
 
@@ -54,7 +66,44 @@ impl ImporterBuilder {
         self
     }
 
+    pub fn importance_ranks(mut self, importance_ranks: &Path) -> Self {
+        self.importance_ranks_path = Some(importance_ranks.to_path_buf());
+        self
+    }
+
+    pub fn wikidata(mut self, wikidata: &Path) -> Self {
+        self.wikidata_path = Some(wikidata.to_path_buf());
+        self
+    }
+
+    /// Limit how many abbreviation permutations are indexed for each street, name, unit, level
+    /// and admin area. Defaults to [`airmail::substitutions::DEFAULT_MAX_PERMUTATIONS`].
+    pub fn max_permutations(mut self, max_permutations: usize) -> Self {
+        self.max_permutations = Some(max_permutations);
+        self
+    }
+
+    /// Load extra substitution dictionaries from a directory, see
+    /// [`airmail::dictionaries::load_dictionary_dir`]. Can be called more than once.
+    pub fn dictionary_dir(mut self, dictionary_dir: &Path) -> Self {
+        self.dictionary_dirs.push(dictionary_dir.to_path_buf());
+        self
+    }
+
     pub async fn build(self) -> Result<Importer> {
+        for dictionary_dir in &self.dictionary_dirs {
+            load_dictionary_dir(dictionary_dir).map_err(|err| {
+                anyhow::anyhow!(
+                    "Failed to load dictionaries from {:?}: {}",
+                    dictionary_dir,
+                    err
+                )
+            })?;
+        }
+        if let Some(max_permutations) = self.max_permutations {
+            set_max_permutations(max_permutations);
+        }
+
         let admin_cache_path = if let Some(admin_cache) = self.admin_cache_path {
             admin_cache
         } else {
@@ -71,7 +120,27 @@ impl ImporterBuilder {
             None
         };
 
-        Importer::new(self.index, admin_cache, wof_db, pip_tree).await
+        let importance_ranks = if let Some(importance_ranks) = self.importance_ranks_path {
+            Some(ImportanceRanks::load(&importance_ranks)?)
+        } else {
+            None
+        };
+
+        let wikidata = if let Some(wikidata) = self.wikidata_path {
+            Some(Wikidata::open(&wikidata)?)
+        } else {
+            None
+        };
+
+        Importer::new(
+            self.index,
+            admin_cache,
+            wof_db,
+            pip_tree,
+            importance_ranks,
+            wikidata,
+        )
+        .await
     }
 }
 
@@ -80,6 +149,8 @@ pub struct Importer {
     indexer_cache: Arc<IndexerCache>,
     wof_db: WhosOnFirst,
     pip_tree: Option<PipTree<ConcisePipResponse>>,
+    importance_ranks: Option<Arc<ImportanceRanks>>,
+    wikidata: Option<Arc<Wikidata>>,
 }
 
 impl Importer {
@@ -88,12 +159,16 @@ impl Importer {
         indexer_cache: IndexerCache,
         wof_db: WhosOnFirst,
         pip_tree: Option<PipTree<ConcisePipResponse>>,
+        importance_ranks: Option<ImportanceRanks>,
+        wikidata: Option<Wikidata>,
     ) -> Result<Self> {
         Ok(Self {
             index,
             indexer_cache: Arc::new(indexer_cache),
             wof_db,
             pip_tree,
+            importance_ranks: importance_ranks.map(Arc::new),
+            wikidata: wikidata.map(Arc::new),
         })
     }
 
@@ -123,11 +198,14 @@ impl Importer {
                 {
                     count += 1;
                     if count % 10000 == 0 {
+                        let permutations = permutation_stats();
                         info!(
-                            "{} POIs parsed in {} seconds, {} per second.",
+                            "{} POIs parsed in {} seconds, {} per second. {} permutations, {} truncated.",
                             count,
                             start.elapsed().as_secs(),
                             count as f64 / start.elapsed().as_secs_f64(),
+                            permutations.produced,
+                            permutations.truncated,
                         );
                     }
                 }
@@ -153,10 +231,12 @@ impl Importer {
             let indexer_cache = self.indexer_cache.clone();
             let wof_db = self.wof_db.clone();
             let pip_tree = self.pip_tree.clone();
+            let importance_ranks = self.importance_ranks.clone();
+            let wikidata = self.wikidata.clone();
 
             handles.push(spawn(async move {
                 let mut counter = 0;
-                while let Ok(poi) = no_admin_receiver.recv() {
+                while let Ok(mut poi) = no_admin_receiver.recv() {
                     counter += 1;
                     if counter % 1000 == 0 {
                         trace!(
@@ -166,6 +246,13 @@ impl Importer {
                         );
                     }
 
+                    poi.importance = importance(&poi.tags, importance_ranks.as_deref());
+                    if let Some(wikidata) = &wikidata {
+                        if let Err(err) = wikidata.enrich(&mut poi) {
+                            warn!("Failed to enrich POI from wikidata, {}", err);
+                        }
+                    }
+
                     match Self::populate_admin_areas(
                         poi,
                         &indexer_cache,
@@ -175,7 +262,8 @@ impl Importer {
                     )
                     .await
                     {
-                        Ok(poi) => {
+                        Ok(mut poi) => {
+                            poi.detect_languages();
                             let schemafied_poi = SchemafiedPoi::from(poi);
                             to_index_sender.send(schemafied_poi).unwrap();
                         }
@@ -193,7 +281,11 @@ impl Importer {
 
         trace!("Waiting for indexing to finish");
         join_all(handles).await;
-        info!("Indexing complete");
+        let permutations = permutation_stats();
+        info!(
+            "Indexing complete. Produced {} permutations, truncated {} fields at the limit.",
+            permutations.produced, permutations.truncated
+        );
 
         Ok(())
     }
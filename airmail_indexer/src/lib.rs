@@ -1,8 +1,10 @@
 pub mod cache;
 pub mod error;
+pub mod importance;
 pub mod importer;
 pub mod pip_tree;
 pub mod query_pip;
+pub mod wikidata;
 pub mod wof;
 
 #[cfg(test)]
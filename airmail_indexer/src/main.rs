@@ -45,6 +45,29 @@ struct Args {
     #[clap(long, short)]
     pip_tree: Option<PathBuf>,
 
+    /// Path to an optional file of `wikidata_id,rank` lines used to override the importance of
+    /// well-known places. Importance is otherwise estimated from each POI's tags.
+    #[clap(long)]
+    importance_ranks: Option<PathBuf>,
+
+    /// Path to a local wikidata extract, either JSON lines in the wikidata dump format or a
+    /// redb table built from one on a previous run. Objects with a `wikidata` tag get the
+    /// entity's labels and aliases added to their names, and its sitelink count factors into
+    /// their importance.
+    #[clap(long)]
+    wikidata: Option<PathBuf>,
+
+    /// Maximum number of abbreviation permutations to index for each street, name, unit, level
+    /// and admin area. The most probable ones are kept.
+    #[clap(long)]
+    max_permutations: Option<usize>,
+
+    /// Extra directories of custom substitution dictionaries, laid out like the bundled
+    /// libpostal ones (`<language>/<category>.txt`). The same directories should be passed to
+    /// the service so queries are expanded consistently.
+    #[clap(long)]
+    dictionaries: Vec<PathBuf>,
+
     /// The loader to use for importing data.
     #[clap(subcommand)]
     loader: Loader,
@@ -64,6 +87,7 @@ enum Loader {
         path: PathBuf,
 
         /// If the nodes are known to be present in the cache (after first run), don't re-add nor check.
+        /// Their addresses are still cached if a cache from an older version doesn't have them.
         #[clap(long, short)]
         nodes_already_cached: bool,
 
@@ -88,6 +112,18 @@ async fn main() -> Result<()> {
     if let Some(pip_tree) = args.pip_tree {
         import_builder = import_builder.pip_tree_cache(&pip_tree);
     }
+    if let Some(importance_ranks) = args.importance_ranks {
+        import_builder = import_builder.importance_ranks(&importance_ranks);
+    }
+    if let Some(wikidata) = args.wikidata {
+        import_builder = import_builder.wikidata(&wikidata);
+    }
+    if let Some(max_permutations) = args.max_permutations {
+        import_builder = import_builder.max_permutations(max_permutations);
+    }
+    for dictionary_dir in &args.dictionaries {
+        import_builder = import_builder.dictionary_dir(dictionary_dir);
+    }
     let importer = import_builder.build().await?;
 
     // Send POIs from the OSM parser to the importer.
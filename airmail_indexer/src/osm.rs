@@ -1,8 +1,12 @@
 use airmail::poi::ToIndexPoi;
-use geo::{Centroid, Coord, LineString, Polygon};
+use geo::{Centroid, Coord, LineInterpolatePoint, LineString, Polygon};
 use log::debug;
 use std::collections::HashMap;
 
+/// Upper bound on the number of addresses synthesized between two numbered nodes of an
+/// interpolation way. Anything above this is almost certainly a tagging mistake.
+const MAX_INTERPOLATED_ADDRESSES: u64 = 1000;
+
 #[allow(clippy::module_name_repetitions)]
 pub struct OsmPoi {
     tags: HashMap<String, String>,
@@ -120,7 +124,13 @@ impl From<OsmPoi> for Option<ToIndexPoi> {
             return None;
         }
 
-        ToIndexPoi::new(
+        let level = poi
+            .tags
+            .get("addr:floor")
+            .or_else(|| poi.tags.get("level"))
+            .map(ToString::to_string);
+
+        let mut poi = ToIndexPoi::new(
             names,
             house_number,
             road,
@@ -129,6 +139,166 @@ impl From<OsmPoi> for Option<ToIndexPoi> {
             lng,
             poi.tags.into_iter().collect(),
         )
-        .ok()
+        .ok()?;
+        poi.level = level;
+        Some(poi)
+    }
+}
+
+/// A node along an `addr:interpolation` way, with its house number and street if it has them.
+pub struct InterpolationNode {
+    pub location: (f64, f64),
+    pub address: Option<(String, Option<String>)>,
+}
+
+/// An OSM `addr:interpolation` way, which describes a range of addresses between the
+/// numbered nodes along it rather than mapping each address individually.
+pub struct OsmInterpolation {
+    tags: HashMap<String, String>,
+    nodes: Vec<InterpolationNode>,
+}
+
+impl OsmInterpolation {
+    /// Create a new `OsmInterpolation` from a way, if it is tagged with `addr:interpolation`.
+    pub fn new_from_way(tags: HashMap<&str, &str>, nodes: Vec<InterpolationNode>) -> Option<Self> {
+        if !tags.contains_key("addr:interpolation") || nodes.len() < 2 {
+            return None;
+        }
+        let tags = tags
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Some(Self { tags, nodes })
+    }
+
+    /// The distance between consecutive house numbers described by the way.
+    fn step(&self) -> Option<u64> {
+        match self.tags.get("addr:interpolation")?.as_str() {
+            "all" => Some(1),
+            "odd" | "even" => Some(2),
+            other => other.parse().ok().filter(|step| *step > 0),
+        }
+    }
+
+    /// Synthesize the addresses between each pair of consecutive numbered nodes of the way.
+    /// The numbered nodes themselves are not included, they are indexed as regular POIs.
+    pub fn index_pois(self) -> Vec<ToIndexPoi> {
+        let Some(step) = self.step() else {
+            debug!(
+                "Unsupported interpolation: {:?}",
+                self.tags.get("addr:interpolation")
+            );
+            return vec![];
+        };
+
+        let numbered = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, node)| {
+                let (house_number, street) = node.address.as_ref()?;
+                let house_number: u64 = house_number.trim().parse().ok()?;
+                Some((index, house_number, street.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut pois = Vec::new();
+        for pair in numbered.windows(2) {
+            let (start_index, start_number, start_street) = &pair[0];
+            let (end_index, end_number, end_street) = &pair[1];
+            if start_number == end_number {
+                continue;
+            }
+            let Some(road) = self
+                .tags
+                .get("addr:street")
+                .cloned()
+                .or_else(|| start_street.clone())
+                .or_else(|| end_street.clone())
+            else {
+                continue;
+            };
+            let span = start_number.abs_diff(*end_number);
+            if span / step > MAX_INTERPOLATED_ADDRESSES {
+                debug!(
+                    "Interpolation range too large: {} to {}",
+                    start_number, end_number
+                );
+                continue;
+            }
+
+            let segment: LineString = self.nodes[*start_index..=*end_index]
+                .iter()
+                .map(|node| Coord::from(node.location))
+                .collect();
+            let mut offset = step;
+            while offset < span {
+                let house_number = if start_number < end_number {
+                    start_number + offset
+                } else {
+                    start_number - offset
+                };
+                #[allow(clippy::cast_precision_loss)]
+                let fraction = offset as f64 / span as f64;
+                offset += step;
+
+                let Some(point) = segment.line_interpolate_point(fraction) else {
+                    continue;
+                };
+                let mut tags = self.tags.clone();
+                tags.insert("addr:housenumber".to_string(), house_number.to_string());
+                tags.insert("addr:street".to_string(), road.clone());
+                if let Ok(poi) = ToIndexPoi::new(
+                    vec![],
+                    Some(house_number.to_string()),
+                    Some(road.clone()),
+                    None,
+                    point.x(),
+                    point.y(),
+                    tags.into_iter().collect(),
+                ) {
+                    pois.push(poi);
+                }
+            }
+        }
+
+        pois
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{InterpolationNode, OsmInterpolation};
+
+    #[test]
+    fn test_interpolate_even() {
+        let tags = HashMap::from([("addr:interpolation", "even")]);
+        let nodes = vec![
+            InterpolationNode {
+                location: (0.0, 0.0),
+                address: Some(("2".to_string(), Some("Main Street".to_string()))),
+            },
+            InterpolationNode {
+                location: (0.0, 0.001),
+                address: None,
+            },
+            InterpolationNode {
+                location: (0.0, 0.002),
+                address: Some(("10".to_string(), Some("Main Street".to_string()))),
+            },
+        ];
+        let pois = OsmInterpolation::new_from_way(tags, nodes)
+            .unwrap()
+            .index_pois();
+        let house_numbers: Vec<_> = pois
+            .iter()
+            .map(|poi| poi.house_number.clone().unwrap())
+            .collect();
+        assert_eq!(house_numbers, vec!["4", "6", "8"]);
+        assert!(pois
+            .iter()
+            .all(|poi| poi.road.as_deref() == Some("Main Street")));
     }
 }
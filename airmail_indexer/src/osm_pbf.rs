@@ -13,16 +13,17 @@ use anyhow::Result;
 use clap::ValueEnum;
 use crossbeam::channel::Sender;
 use log::{info, warn};
-use osmpbf::{Element, ElementReader};
+use osmpbf::{Element, ElementReader, Way};
 
-use crate::osm::OsmPoi;
+use crate::osm::{InterpolationNode, OsmInterpolation, OsmPoi};
 
 /// An OpenStreetMap PBF file loader.
 ///
 /// OSM PBF contains nodes, ways and relations. This loader extracts points of interest from
 /// nodes and ways. The location of a node or way may be present in the data, or
 /// may require a lookup from other nodes. To prevent a full scan, the location of all nodes
-/// is cached.
+/// is cached, along with the address of any node that has one so that `addr:interpolation`
+/// ways can be expanded into individual addresses.
 pub struct OsmPbf {
     pbf_path: PathBuf,
     nodes_already_cached: bool,
@@ -49,16 +50,12 @@ impl OsmPbf {
     }
 
     pub fn parse_osm(self) -> Result<()> {
-        // This call is very expensive as everything has to be read from the PBF file,
-        // decompressing and parsing.
-        if !self.nodes_already_cached {
-            info!("Generating OSM node map from: {}", self.pbf_path.display());
-            self.cache_nodes_for_ways()?;
-        }
+        self.prepare_node_cache()?;
 
         let count_ways = AtomicUsize::new(0);
         let count_nodes = AtomicUsize::new(0);
         let count_dense_nodes = AtomicUsize::new(0);
+        let count_interpolated = AtomicUsize::new(0);
 
         info!("Parsing POIs");
 
@@ -109,6 +106,11 @@ impl OsmPbf {
                         return 0;
                     }
 
+                    // Address interpolation ways are expanded into the addresses along them.
+                    if way.tags().any(|(key, _)| key == "addr:interpolation") {
+                        return self.interpolate_way(&way, &count_interpolated);
+                    }
+
                     // Attempt to get the location from the way from the underlying way data,
                     // this requires the way is stored with the option LocationsOnWays enabled.
                     let mut way_points = way
@@ -158,10 +160,11 @@ impl OsmPbf {
         let count_ways = count_ways.load(Ordering::Relaxed);
         let count_nodes = count_nodes.load(Ordering::Relaxed);
         let count_dense_nodes = count_dense_nodes.load(Ordering::Relaxed);
+        let count_interpolated = count_interpolated.load(Ordering::Relaxed);
 
         info!(
-            "Loaded {} interesting pois, made up of {} dense nodes, {} nodes, and {} ways",
-            pois, count_dense_nodes, count_nodes, count_ways
+            "Loaded {} interesting pois, made up of {} dense nodes, {} nodes, {} ways, and {} interpolated addresses",
+            pois, count_dense_nodes, count_nodes, count_ways, count_interpolated
         );
 
         if count_ways == 0 {
@@ -171,7 +174,105 @@ impl OsmPbf {
         Ok(())
     }
 
-    fn cache_nodes_for_ways(&self) -> Result<()> {
+    /// Expand an `addr:interpolation` way into the addresses along it, using the cached
+    /// locations and addresses of its nodes, and send them for indexing.
+    fn interpolate_way(&self, way: &Way, count_interpolated: &AtomicUsize) -> u64 {
+        let locations = way
+            .node_locations()
+            .map(|n| (n.lat(), n.lon()))
+            .collect::<Vec<(f64, f64)>>();
+
+        let mut nodes = Vec::new();
+        for (index, node_id) in way.refs().enumerate() {
+            let location = if let Some(location) = locations.get(index) {
+                *location
+            } else if let Ok(Some(location)) = self.indexer_cache.query_node_location(node_id) {
+                location
+            } else {
+                continue;
+            };
+            let address = self
+                .indexer_cache
+                .query_node_address(node_id)
+                .ok()
+                .flatten();
+            nodes.push(InterpolationNode { location, address });
+        }
+
+        let tags = way.tags().collect::<HashMap<_, _>>();
+        let pois = OsmInterpolation::new_from_way(tags, nodes)
+            .map(OsmInterpolation::index_pois)
+            .unwrap_or_default();
+
+        count_interpolated.fetch_add(pois.len(), Ordering::Relaxed);
+        let count = pois.len() as u64;
+        for poi in pois {
+            self.sender.send(poi).expect("sender failed");
+        }
+        count
+    }
+
+    /// Cache the address of a node, if it has one, for use by interpolation ways.
+    fn cache_node_address<'a>(&self, node_id: i64, tags: impl Iterator<Item = (&'a str, &'a str)>) {
+        let mut house_number = None;
+        let mut street = None;
+        for (key, value) in tags {
+            match key {
+                "addr:housenumber" => house_number = Some(value.to_string()),
+                "addr:street" => street = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        if let Some(house_number) = house_number {
+            let _ = self
+                .indexer_cache
+                .buffered_write_item(WofCacheItem::NodeAddress(node_id, house_number, street))
+                .map_err(|e| {
+                    warn!("Error writing node address to cache: {}", e);
+                });
+        }
+    }
+
+    /// Cache node locations unless they're known to be cached already, and node addresses
+    /// unless the cache has them.
+    fn prepare_node_cache(&self) -> Result<()> {
+        // This call is very expensive as everything has to be read from the PBF file,
+        // decompressing and parsing.
+        if !self.nodes_already_cached {
+            info!("Generating OSM node map from: {}", self.pbf_path.display());
+            self.cache_nodes_for_ways(true)?;
+        } else if !self.indexer_cache.has_node_addresses()? {
+            info!(
+                "Caching OSM node addresses from: {}",
+                self.pbf_path.display()
+            );
+            self.cache_nodes_for_ways(false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Cache the location of a node, if `locations` is set, and its address if it has one.
+    fn cache_node<'a>(
+        &self,
+        node_id: i64,
+        location: (f64, f64),
+        tags: impl Iterator<Item = (&'a str, &'a str)>,
+        locations: bool,
+    ) {
+        if locations {
+            let _ = self
+                .indexer_cache
+                .buffered_write_item(WofCacheItem::NodeLocation(node_id, location))
+                .map_err(|e| {
+                    warn!("Error writing node location to cache: {}", e);
+                });
+        }
+        self.cache_node_address(node_id, tags);
+    }
+
+    /// Cache the addresses of nodes, and their locations unless the cache already has them.
+    fn cache_nodes_for_ways(&self, locations: bool) -> Result<()> {
         // Increase buffer to reduce writes to disk
         self.indexer_cache.buffer_size(10_000_000)?;
 
@@ -179,23 +280,11 @@ impl OsmPbf {
         let cached_count = ElementReader::from_path(&self.pbf_path)?.par_map_reduce(
             |element| match element {
                 Element::Node(node) => {
-                    let location = (node.lat(), node.lon());
-                    let _ = self
-                        .indexer_cache
-                        .buffered_write_item(WofCacheItem::NodeLocation(node.id(), location))
-                        .map_err(|e| {
-                            warn!("Error writing node location to cache: {}", e);
-                        });
+                    self.cache_node(node.id(), (node.lat(), node.lon()), node.tags(), locations);
                     1
                 }
                 Element::DenseNode(node) => {
-                    let location = (node.lat(), node.lon());
-                    let _ = self
-                        .indexer_cache
-                        .buffered_write_item(WofCacheItem::NodeLocation(node.id(), location))
-                        .map_err(|e| {
-                            warn!("Error writing node location to cache: {}", e);
-                        });
+                    self.cache_node(node.id(), (node.lat(), node.lon()), node.tags(), locations);
                     1
                 }
                 _ => 0,
@@ -206,8 +295,9 @@ impl OsmPbf {
 
         // Revert buffer size to default
         self.indexer_cache.buffer_size_default()?;
+        self.indexer_cache.set_has_node_addresses()?;
 
-        info!("{} node locations are cached", cached_count);
+        info!("{} nodes are cached", cached_count);
 
         Ok(())
     }
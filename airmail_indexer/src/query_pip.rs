@@ -122,7 +122,8 @@ async fn query_names(admin_id: u64, wof_db: &WhosOnFirst) -> Option<(u64, Vec<St
             "zho" => true, // Chinese.
             _ => false,
         })
-        .map(|place_name| deunicode::deunicode(&place_name.name).to_lowercase())
+        // Names are transliterated when they're indexed, keep the original script here.
+        .map(|place_name| place_name.name.to_lowercase())
         .collect::<HashSet<_>>()
         .iter()
         .cloned()
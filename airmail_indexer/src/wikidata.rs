@@ -0,0 +1,249 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use airmail::poi::ToIndexPoi;
+use anyhow::Result;
+use log::{info, trace, warn};
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const TABLE_ENTITIES: TableDefinition<&str, &[u8]> = TableDefinition::new("wikidata_entities");
+
+/// Languages whose labels and aliases are kept. These match the languages we import admin
+/// names for from Who's On First.
+const WIKIDATA_LANGUAGES: [&str; 18] = [
+    "ar", "da", "de", "fr", "fi", "hu", "el", "it", "nl", "pt", "ru", "ro", "es", "en", "sv", "ta",
+    "tr", "zh",
+];
+
+/// Sitelink count at which an entity is considered maximally important.
+const MAX_SITELINKS: f64 = 300.0;
+
+/// Remove repeated values, keeping the first of each.
+fn dedup(values: &mut Vec<String>) {
+    let mut seen = HashSet::new();
+    values.retain(|value| seen.insert(value.clone()));
+}
+
+/// The subset of a wikidata entity that's useful for geocoding.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WikidataEntity {
+    pub labels: Vec<String>,
+    pub aliases: Vec<String>,
+    pub sitelinks: u32,
+}
+
+impl WikidataEntity {
+    /// Parse an entity from the wikidata JSON dump format, returning its ID and contents.
+    fn from_json(value: &Value) -> Option<(String, Self)> {
+        let id = value.get("id")?.as_str()?.to_string();
+
+        let mut labels = Vec::new();
+        let mut aliases = Vec::new();
+        for lang in WIKIDATA_LANGUAGES {
+            if let Some(label) = value
+                .get("labels")
+                .and_then(|labels| labels.get(lang))
+                .and_then(|label| label.get("value"))
+                .and_then(Value::as_str)
+            {
+                labels.push(label.to_string());
+            }
+            if let Some(lang_aliases) = value
+                .get("aliases")
+                .and_then(|aliases| aliases.get(lang))
+                .and_then(Value::as_array)
+            {
+                aliases.extend(
+                    lang_aliases
+                        .iter()
+                        .filter_map(|alias| alias.get("value")?.as_str())
+                        .map(ToString::to_string),
+                );
+            }
+        }
+        dedup(&mut labels);
+        dedup(&mut aliases);
+
+        let sitelinks = match value.get("sitelinks") {
+            Some(Value::Object(sitelinks)) => sitelinks.len(),
+            Some(Value::Number(count)) => count.as_u64().unwrap_or_default() as usize,
+            _ => 0,
+        };
+
+        Some((
+            id,
+            Self {
+                labels,
+                aliases,
+                sitelinks: sitelinks.try_into().unwrap_or(u32::MAX),
+            },
+        ))
+    }
+
+    /// Importance implied by how many wikipedias link to this entity, from 0 to 1.
+    pub fn importance(&self) -> f64 {
+        (f64::from(self.sitelinks).ln_1p() / MAX_SITELINKS.ln_1p()).min(1.0)
+    }
+}
+
+/// A local wikidata extract used to enrich OSM objects that carry a `wikidata=Q…` tag.
+pub struct Wikidata {
+    database: Database,
+}
+
+impl Wikidata {
+    /// Open a wikidata extract. This can either be a prebuilt redb table (`.redb`), or a JSON
+    /// lines file in the wikidata dump format, in which case the redb table is built next to
+    /// it on first use. The table is built under a temporary name and only renamed once it's
+    /// complete, so an interrupted build is started over rather than reused.
+    pub fn open(path: &Path) -> Result<Self> {
+        if path.extension().is_some_and(|ext| ext == "redb") {
+            trace!("Opening wikidata table at {:?}", path);
+            return Ok(Self {
+                database: Database::open(path)?,
+            });
+        }
+
+        let redb_path = path.with_extension("redb");
+        if redb_path.exists() {
+            trace!("Opening wikidata table at {:?}", redb_path);
+            return Ok(Self {
+                database: Database::open(&redb_path)?,
+            });
+        }
+
+        info!("Building wikidata table {:?} from {:?}", redb_path, path);
+        let partial_path = path.with_extension("redb.partial");
+        if partial_path.exists() {
+            std::fs::remove_file(&partial_path)?;
+        }
+        let database = Database::create(&partial_path)?;
+        Self::import_json_lines(&database, path)?;
+        drop(database);
+        std::fs::rename(&partial_path, &redb_path)?;
+        Ok(Self {
+            database: Database::open(&redb_path)?,
+        })
+    }
+
+    fn import_json_lines(database: &Database, path: &Path) -> Result<()> {
+        let reader = BufReader::new(File::open(path)?);
+        let txn = database.begin_write()?;
+        let mut count = 0;
+        {
+            let mut table = txn.open_table(TABLE_ENTITIES)?;
+            for line in reader.lines() {
+                let line = line?;
+                // The full dump is one big JSON array with an entity per line.
+                let line = line.trim().trim_end_matches(',');
+                if line.is_empty() || line == "[" || line == "]" {
+                    continue;
+                }
+                let value: Value = match serde_json::from_str(line) {
+                    Ok(value) => value,
+                    Err(err) => {
+                        warn!("Skipping malformed wikidata line: {}", err);
+                        continue;
+                    }
+                };
+                if let Some((id, entity)) = WikidataEntity::from_json(&value) {
+                    table.insert(id.as_str(), bincode::serialize(&entity)?.as_slice())?;
+                    count += 1;
+                }
+            }
+        }
+        txn.commit()?;
+        info!("Imported {} wikidata entities", count);
+        Ok(())
+    }
+
+    /// Lookup an entity by its wikidata ID.
+    pub fn entity(&self, id: &str) -> Result<Option<WikidataEntity>> {
+        let txn = self.database.begin_read()?;
+        let table = txn.open_table(TABLE_ENTITIES)?;
+        if let Some(entity) = table.get(id)? {
+            return Ok(Some(bincode::deserialize(entity.value())?));
+        }
+        Ok(None)
+    }
+
+    /// Add the labels and aliases of the POI's wikidata entity to its names, and raise its
+    /// importance based on the entity's sitelink count.
+    pub fn enrich(&self, poi: &mut ToIndexPoi) -> Result<()> {
+        let Some(id) = poi
+            .tags
+            .iter()
+            .find(|(key, _)| key == "wikidata")
+            .map(|(_, value)| value.trim().to_string())
+        else {
+            return Ok(());
+        };
+        let Some(entity) = self.entity(&id)? else {
+            return Ok(());
+        };
+
+        poi.importance = poi.importance.max(entity.importance());
+        for name in entity.labels.into_iter().chain(entity.aliases) {
+            if !poi.names.contains(&name) {
+                poi.names.push(name);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Wikidata, WikidataEntity};
+
+    #[test]
+    fn test_entity_from_dump_json() {
+        let value = serde_json::json!({
+            "id": "Q1140",
+            "labels": {
+                "en": {"language": "en", "value": "Space Needle"},
+                "fr": {"language": "fr", "value": "Space Needle"},
+                "es": {"language": "es", "value": "Aguja Espacial"},
+                "ja": {"language": "ja", "value": "スペースニードル"}
+            },
+            "aliases": {
+                "en": [{"language": "en", "value": "The Needle"}]
+            },
+            "sitelinks": {
+                "enwiki": {"site": "enwiki", "title": "Space Needle"},
+                "frwiki": {"site": "frwiki", "title": "Space Needle"}
+            }
+        });
+        let (id, entity) = WikidataEntity::from_json(&value).unwrap();
+        assert_eq!(id, "Q1140");
+        assert_eq!(entity.labels, vec!["Space Needle", "Aguja Espacial"]);
+        assert_eq!(entity.aliases, vec!["The Needle"]);
+        assert_eq!(entity.sitelinks, 2);
+        assert!(entity.importance() > 0.0 && entity.importance() < 1.0);
+    }
+
+    #[test]
+    fn test_build_replaces_partial_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wikidata.jsonl");
+        std::fs::write(
+            &path,
+            r#"{"id": "Q1140", "labels": {"en": {"language": "en", "value": "Space Needle"}}}"#,
+        )
+        .unwrap();
+        // Left behind by a build that was interrupted.
+        std::fs::write(dir.path().join("wikidata.redb.partial"), "not a table").unwrap();
+
+        let wikidata = Wikidata::open(&path).unwrap();
+        let entity = wikidata.entity("Q1140").unwrap().unwrap();
+        assert_eq!(entity.labels, vec!["Space Needle"]);
+        assert!(dir.path().join("wikidata.redb").exists());
+        assert!(!dir.path().join("wikidata.redb.partial").exists());
+    }
+}
@@ -1,22 +1,29 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
 
-use airmail::{index::AirmailIndex, poi::AirmailPoi};
-use anyhow::Result;
-use axum::{
-    extract::{Query, State},
-    response::IntoResponse,
-    Json,
+use airmail::{
+    bbox::parse_bbox, explain::SearchExplanation, parser::AddressComponent, poi::AirmailPoi,
 };
-use deunicode::deunicode;
-use geo::{Coord, Rect};
+use anyhow::Result;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use futures_util::future::join_all;
 #[cfg(feature = "invasive_logging")]
 use log::debug;
+use log::warn;
 use serde::{Deserialize, Serialize};
 
-use crate::error::AirmailServiceError;
+use crate::{
+    datasets::Datasets,
+    error::AirmailServiceError,
+    metrics::observe_search,
+    validation::{validate_query, Parameters, Validated},
+};
+
+/// The most results returned by a search, after merging results from every dataset.
+const MAX_RESULTS: usize = 10;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchQueryParams {
+    #[serde(default)]
     q: String,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -27,6 +34,15 @@ pub struct SearchQueryParams {
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     bbox: Option<String>,
+
+    /// Explain how the query was planned and why each result matched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    debug: Option<bool>,
+
+    /// Comma-separated names of the datasets to search. Defaults to every dataset covering the
+    /// bbox, or all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dataset: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,40 +54,100 @@ pub struct Response {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct MetadataResponse {
     query: SearchQueryParams,
+
+    /// Explanations keyed by dataset name.
+    #[serde(default, skip_serializing_if = "Option::is_none", skip_deserializing)]
+    debug: Option<BTreeMap<String, SearchExplanation>>,
 }
 
-fn parse_bbox(s: &str) -> Option<Rect> {
-    let mut parts = s.split(',');
-    let min_lng: f64 = parts.next()?.parse().ok()?;
-    let min_lat: f64 = parts.next()?.parse().ok()?;
-    let max_lng: f64 = parts.next()?.parse().ok()?;
-    let max_lat: f64 = parts.next()?.parse().ok()?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseQueryParams {
+    #[serde(default)]
+    q: String,
 
-    Some(Rect::new(
-        Coord {
-            y: min_lat,
-            x: min_lng,
-        },
-        Coord {
-            y: max_lat,
-            x: max_lng,
-        },
-    ))
+    /// The dataset whose admin areas are used to recognize localities. Defaults to the first.
+    #[serde(default)]
+    dataset: Option<String>,
 }
 
-pub async fn search(
-    Query(params): Query<SearchQueryParams>,
-    State(index): State<Arc<AirmailIndex>>,
-) -> Result<impl IntoResponse, AirmailServiceError> {
-    let query = deunicode(params.q.trim()).to_lowercase();
+impl Parameters for SearchQueryParams {
+    const NAMES: &'static [&'static str] = &["q", "tags", "leniency", "bbox", "debug", "dataset"];
+
+    fn validate(&self) -> Result<(), AirmailServiceError> {
+        validate_query(&self.q)?;
+        if let Some(bbox) = &self.bbox {
+            parse_bbox(bbox)?;
+        }
+        Ok(())
+    }
+}
+
+impl Parameters for ParseQueryParams {
+    const NAMES: &'static [&'static str] = &["q", "dataset"];
+
+    fn validate(&self) -> Result<(), AirmailServiceError> {
+        validate_query(&self.q)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseResponse {
+    query: String,
+    components: Vec<AddressComponent>,
+}
+
+/// Search the datasets selected by `params`, returning the merged results and, if `params`
+/// asks for them, explanations keyed by dataset name.
+pub async fn search_datasets(
+    datasets: &Datasets,
+    params: &SearchQueryParams,
+) -> Result<(Vec<AirmailPoi>, Option<BTreeMap<String, SearchExplanation>>), AirmailServiceError> {
+    // The index handles transliteration itself, so it can also match the query as written.
+    let query = params.q.trim();
     let tags: Option<Vec<String>> = params
         .tags
         .clone()
         .map(|s| s.split(',').map(std::string::ToString::to_string).collect());
     let leniency = params.leniency.unwrap_or_default();
-    let bbox = params.bbox.clone().and_then(|s| parse_bbox(&s));
+    let bbox = params.bbox.as_deref().map(parse_bbox).transpose()?;
+
+    let debug = params.debug.unwrap_or_default();
 
-    let results = index.search(&query, leniency, tags, bbox, &[]).await?;
+    let selected = datasets.select(params.dataset.as_deref(), bbox.as_ref())?;
+    let searches = selected.iter().map(|dataset| {
+        let tags = tags.clone();
+        async move {
+            let search = dataset
+                .index()
+                .search_explained(query, leniency, tags, bbox, &[], debug)
+                .await;
+            (dataset.name.clone(), search)
+        }
+    });
+
+    // Results from every dataset are merged, see `rank`. A dataset failing, like a remote index
+    // being unreachable, only fails the search if they all do.
+    let mut results: Vec<(AirmailPoi, f32)> = Vec::new();
+    let mut explanations = BTreeMap::new();
+    let mut last_error = None;
+    for (name, search) in join_all(searches).await {
+        match search {
+            Ok((dataset_results, explanation)) => {
+                results.extend(dataset_results);
+                explanations.insert(name, explanation);
+            }
+            Err(err) => {
+                warn!("Search of dataset {} failed: {:#}", name, err);
+                last_error = Some(err);
+            }
+        }
+    }
+    if let (Some(err), true) = (last_error, explanations.is_empty()) {
+        return Err(err.into());
+    }
+    rank(&mut results);
+    results.truncate(MAX_RESULTS);
+    observe_search(results.len());
 
     #[cfg(feature = "invasive_logging")]
     {
@@ -84,13 +160,124 @@ pub async fn search(
         );
     }
 
+    Ok((
+        results.into_iter().map(|(poi, _)| poi).collect(),
+        debug.then_some(explanations),
+    ))
+}
+
+/// Order results from any number of datasets, best first. Scores from different indices can't be
+/// compared, since each index has its own term statistics, so results are ordered by confidence
+/// and their scores only break ties.
+fn rank(results: &mut [(AirmailPoi, f32)]) {
+    results.sort_by(|(a, a_score), (b, b_score)| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then_with(|| b_score.total_cmp(a_score))
+    });
+}
+
+pub async fn search(
+    Validated(params): Validated<SearchQueryParams>,
+    State(datasets): State<Datasets>,
+) -> Result<impl IntoResponse, AirmailServiceError> {
+    let (features, debug) = search_datasets(&datasets, &params).await?;
     let response = Response {
-        metadata: MetadataResponse { query: params },
-        features: results
-            .into_iter()
-            .map(|(results, _)| results)
-            .collect::<Vec<AirmailPoi>>(),
+        metadata: MetadataResponse {
+            query: params,
+            debug,
+        },
+        features,
     };
 
     Ok(Json(serde_json::to_value(response)?))
 }
+
+pub async fn parse(
+    Validated(params): Validated<ParseQueryParams>,
+    State(datasets): State<Datasets>,
+) -> Result<impl IntoResponse, AirmailServiceError> {
+    let dataset = datasets.select(params.dataset.as_deref(), None)?[0];
+    let components = dataset.index().parse(params.q.trim()).await?;
+    let response = ParseResponse {
+        query: params.q,
+        components,
+    };
+    Ok(Json(serde_json::to_value(response)?))
+}
+
+/// Liveness: the process is up and serving requests.
+pub async fn health() -> impl IntoResponse {
+    "ok"
+}
+
+#[derive(Clone)]
+pub struct ReadyState {
+    pub datasets: Datasets,
+    pub canary_query: String,
+}
+
+/// Readiness: every index can serve a search, and remote indices' servers are reachable.
+pub async fn ready(State(state): State<ReadyState>) -> impl IntoResponse {
+    for dataset in state.datasets.iter() {
+        if let Err(err) = dataset.index().check_ready(&state.canary_query).await {
+            warn!(
+                "Readiness check of dataset {} failed: {:#}",
+                dataset.name, err
+            );
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("{}: {}", dataset.name, err),
+            );
+        }
+    }
+    (StatusCode::OK, "ready".to_string())
+}
+
+/// The status of each dataset, keyed by name.
+pub async fn status(
+    State(datasets): State<Datasets>,
+) -> Result<impl IntoResponse, AirmailServiceError> {
+    let mut statuses = BTreeMap::new();
+    for dataset in datasets.iter() {
+        statuses.insert(dataset.name.clone(), dataset.index().status().await?);
+    }
+    Ok(Json(serde_json::to_value(statuses)?))
+}
+
+#[cfg(test)]
+mod test {
+    use airmail::poi::AirmailPoi;
+
+    use super::rank;
+
+    fn result(name: &str, confidence: f64, score: f32) -> (AirmailPoi, f32) {
+        let tags = vec![("name".to_string(), name.to_string())];
+        let mut poi = AirmailPoi::new("test".to_string(), 0.0, 0.0, tags).unwrap();
+        poi.confidence = confidence;
+        (poi, score)
+    }
+
+    #[test]
+    fn test_rank() {
+        // A small index scores its matches far higher than a big one does.
+        let mut results = vec![
+            result("partial match in small index", 0.5, 40.0),
+            result("exact match in big index", 1.0, 8.0),
+            result("exact match in small index", 1.0, 30.0),
+        ];
+        rank(&mut results);
+        let names = results
+            .iter()
+            .map(|(poi, _)| poi.tags[0].1.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "exact match in small index",
+                "exact match in big index",
+                "partial match in small index"
+            ]
+        );
+    }
+}
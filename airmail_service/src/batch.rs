@@ -0,0 +1,315 @@
+use std::{convert::Infallible, io};
+
+use axum::{body::Body, extract::State, http::header, response::IntoResponse};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde_json::{json, Value};
+use tokio_util::{
+    bytes::BytesMut,
+    codec::{Decoder, FramedRead},
+    io::StreamReader,
+};
+
+use crate::{
+    api::{search_datasets, SearchQueryParams},
+    datasets::Datasets,
+    error::AirmailServiceError,
+    metrics::observe_error,
+    validation::Parameters,
+};
+
+/// The largest item of a batch accepted, in bytes. Items are searched as they're read, so this
+/// rather than the length of the batch bounds the memory it takes.
+const MAX_ITEM_BYTES: usize = 64 * 1024;
+
+#[derive(Clone)]
+pub struct BatchState {
+    pub datasets: Datasets,
+    /// How many searches of a batch are run at once.
+    pub parallelism: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    #[default]
+    Unknown,
+    Ndjson,
+    Array,
+    ArrayClosed,
+}
+
+fn invalid_batch(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn too_long() -> String {
+    format!("items must be at most {MAX_ITEM_BYTES} bytes")
+}
+
+fn parse_json(item: &[u8]) -> Result<Value, AirmailServiceError> {
+    serde_json::from_slice(item)
+        .map_err(|err| AirmailServiceError::InvalidParameter(format!("malformed item: {err}")))
+}
+
+/// Splits a batch into its items as it's read. A batch is either a JSON array or
+/// newline-delimited JSON with one item per line. An item that isn't valid JSON only fails its
+/// own item, as does an NDJSON line that's too long, but an array that's malformed or has an
+/// item that's too long fails the rest of the batch.
+#[derive(Debug, Default)]
+struct BatchCodec {
+    layout: Layout,
+    /// How much of the buffer has been scanned for the end of the current item.
+    scanned: usize,
+    /// How deeply nested in the current array item the scan is.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    array_items: usize,
+    /// Whether the rest of an NDJSON line that's too long is being dropped.
+    skipping: bool,
+}
+
+type Item = Result<Value, AirmailServiceError>;
+
+impl BatchCodec {
+    fn decode_line(&mut self, buf: &mut BytesMut) -> Option<Item> {
+        loop {
+            let Some(newline) = buf[self.scanned..].iter().position(|b| *b == b'\n') else {
+                if self.skipping || buf.len() > MAX_ITEM_BYTES {
+                    buf.clear();
+                    self.skipping = true;
+                }
+                self.scanned = buf.len();
+                return None;
+            };
+            let line = buf.split_to(self.scanned + newline + 1);
+            self.scanned = 0;
+            if std::mem::take(&mut self.skipping) || line.len() > MAX_ITEM_BYTES + 1 {
+                return Some(Err(AirmailServiceError::InvalidParameter(too_long())));
+            }
+            let line = line.trim_ascii();
+            if !line.is_empty() {
+                return Some(parse_json(line));
+            }
+        }
+    }
+
+    fn decode_array_item(&mut self, buf: &mut BytesMut) -> io::Result<Option<Item>> {
+        let mut end = None;
+        for (index, byte) in buf.iter().enumerate().skip(self.scanned) {
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match byte {
+                b'"' => self.in_string = true,
+                b'[' | b'{' => self.depth += 1,
+                b']' | b'}' if self.depth > 0 => self.depth -= 1,
+                b']' | b',' if self.depth == 0 => {
+                    end = Some((index, *byte == b']'));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let Some((index, closed)) = end else {
+            if buf.len() > MAX_ITEM_BYTES {
+                return Err(invalid_batch(too_long()));
+            }
+            self.scanned = buf.len();
+            return Ok(None);
+        };
+        let item = buf.split_to(index + 1);
+        self.scanned = 0;
+        let item = item[..index].trim_ascii();
+        if closed {
+            self.layout = Layout::ArrayClosed;
+            if item.is_empty() && self.array_items == 0 {
+                return Ok(None);
+            }
+        }
+        if item.is_empty() {
+            return Err(invalid_batch("empty item in array".to_string()));
+        }
+        self.array_items += 1;
+        Ok(Some(parse_json(item)))
+    }
+}
+
+impl Decoder for BatchCodec {
+    type Item = Item;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Item>> {
+        if self.layout == Layout::Unknown {
+            let Some(start) = buf.iter().position(|b| !b.is_ascii_whitespace()) else {
+                buf.clear();
+                return Ok(None);
+            };
+            if buf[start] == b'[' {
+                let _ = buf.split_to(start + 1);
+                self.layout = Layout::Array;
+            } else {
+                self.layout = Layout::Ndjson;
+            }
+        }
+        match self.layout {
+            Layout::Unknown => Ok(None),
+            Layout::Ndjson => Ok(self.decode_line(buf)),
+            Layout::Array => self.decode_array_item(buf),
+            Layout::ArrayClosed => {
+                if buf.iter().any(|b| !b.is_ascii_whitespace()) {
+                    return Err(invalid_batch("unexpected data after the array".to_string()));
+                }
+                buf.clear();
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Item>> {
+        if let Some(item) = self.decode(buf)? {
+            return Ok(Some(item));
+        }
+        match self.layout {
+            // The last line needn't end with a newline.
+            Layout::Ndjson if !buf.is_empty() || self.skipping => {
+                buf.extend_from_slice(b"\n");
+                Ok(self.decode_line(buf))
+            }
+            Layout::Array => Err(invalid_batch("the array isn't closed".to_string())),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Read an item's search parameters, which are the same as `/search`'s, plus an `id` that's
+/// echoed back so results can be matched to their inputs.
+fn parse_item(item: Value) -> Result<SearchQueryParams, AirmailServiceError> {
+    let Value::Object(mut fields) = item else {
+        return Err(AirmailServiceError::InvalidParameter(
+            "items must be objects".to_string(),
+        ));
+    };
+    fields.remove("id");
+    if let Some(name) = fields
+        .keys()
+        .find(|name| !SearchQueryParams::NAMES.contains(&name.as_str()))
+    {
+        return Err(AirmailServiceError::UnknownParameter(name.clone()));
+    }
+    let params: SearchQueryParams = serde_json::from_value(Value::Object(fields))
+        .map_err(|err| AirmailServiceError::InvalidParameter(err.to_string()))?;
+    params.validate()?;
+    Ok(params)
+}
+
+/// Search for one item, returning its line of output. Failures are reported in the line rather
+/// than failing the batch.
+async fn search_item(datasets: &Datasets, item: Result<Value, AirmailServiceError>) -> Value {
+    let id = item.as_ref().ok().and_then(|item| item.get("id").cloned());
+    let result = match item.and_then(parse_item) {
+        Ok(params) => search_datasets(datasets, &params).await,
+        Err(err) => Err(err),
+    };
+    let mut line = match result {
+        Ok((features, None)) => json!({ "features": features }),
+        Ok((features, Some(debug))) => json!({ "features": features, "debug": debug }),
+        Err(err) => {
+            observe_error(&err);
+            err.body()
+        }
+    };
+    if let Some(id) = id {
+        line["id"] = id;
+    }
+    line
+}
+
+/// Geocode a batch of queries, streaming a line of NDJSON back for each in the order they were
+/// given. Items are read from the request as they're searched, so a batch can be any length. A
+/// batch that's empty or fails before its first item is rejected outright, later failures end
+/// the response with a line describing them.
+pub async fn batch(
+    State(state): State<BatchState>,
+    body: Body,
+) -> Result<impl IntoResponse, AirmailServiceError> {
+    let reader = StreamReader::new(body.into_data_stream().map_err(io::Error::other));
+    let mut items = FramedRead::new(reader, BatchCodec::default())
+        .map_err(|err| AirmailServiceError::InvalidBatch(err.to_string()));
+    let first = match items.next().await {
+        Some(Ok(item)) => item,
+        Some(Err(err)) => return Err(err),
+        None => return Err(AirmailServiceError::InvalidBatch("no items".to_string())),
+    };
+    let datasets = state.datasets;
+    let lines = stream::once(async { Ok(first) })
+        .chain(items)
+        .map(move |item| {
+            let datasets = datasets.clone();
+            async move { search_item(&datasets, item.and_then(|item| item)).await }
+        })
+        .buffered(state.parallelism)
+        .map(|line| Ok::<_, Infallible>(format!("{line}\n")));
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use axum::body::Bytes;
+    use futures_util::{stream, StreamExt};
+    use serde_json::json;
+    use tokio_util::{codec::FramedRead, io::StreamReader};
+
+    use super::{BatchCodec, Item, MAX_ITEM_BYTES};
+
+    /// Split a batch that arrives a byte at a time.
+    async fn split_batch(body: &str) -> Vec<io::Result<Item>> {
+        let bytes = body
+            .bytes()
+            .map(|byte| Ok::<_, io::Error>(Bytes::from(vec![byte])))
+            .collect::<Vec<_>>();
+        FramedRead::new(
+            StreamReader::new(stream::iter(bytes)),
+            BatchCodec::default(),
+        )
+        .collect()
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_split_batch() {
+        let items = split_batch(r#" [{"q": "a, [b]"}, {"q": "\"]", "id": 2}] "#).await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[1].as_ref().unwrap().as_ref().unwrap(),
+            &json!({"q": "\"]", "id": 2})
+        );
+
+        let items = split_batch("{\"q\": \"a\"}\n\nnot json\n{\"q\": \"c\"}").await;
+        assert_eq!(items.len(), 3);
+        assert!(items[1].as_ref().unwrap().is_err());
+        assert!(items[2].as_ref().unwrap().is_ok());
+
+        let long = format!("{{\"q\": \"{}\"}}", "a".repeat(MAX_ITEM_BYTES));
+        let items = split_batch(&format!("{long}\n{{\"q\": \"b\"}}\n")).await;
+        assert_eq!(items.len(), 2);
+        assert!(items[0].as_ref().unwrap().is_err());
+        assert!(items[1].as_ref().unwrap().is_ok());
+        assert!(split_batch(&format!("[{long}]")).await[0].is_err());
+
+        assert!(split_batch("[{\"q\": ").await[0].is_err());
+        assert!(split_batch("[{\"q\": \"a\"},]").await[1].is_err());
+        assert!(split_batch("[]").await.is_empty());
+        assert!(split_batch("\n").await.is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use airmail::{
+    bbox::{parse_bbox, Bbox},
+    index::AirmailIndex,
+};
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use tokio::{sync::Mutex, task::spawn_blocking, time::interval};
+
+use crate::error::AirmailServiceError;
+
+/// A named index, optionally limited to the area it covers.
+pub struct Dataset {
+    pub name: String,
+    /// Where the index is loaded from, a local path or a URL.
+    location: String,
+    index: RwLock<Arc<AirmailIndex>>,
+    /// The `meta.json` of the loaded version of the index.
+    meta: Mutex<String>,
+    /// Searches with a bbox outside of this area skip the dataset.
+    pub coverage: Option<Bbox>,
+}
+
+impl Dataset {
+    async fn open(name: String, location: String) -> Result<Self> {
+        debug!("Loading index {} from {}", name, location);
+        let meta = AirmailIndex::read_meta(&location).await?;
+        let index = {
+            let location = location.clone();
+            Arc::new(spawn_blocking(move || AirmailIndex::open(&location)).await??)
+        };
+        info!(
+            "Loaded {} docs from index {}",
+            index.num_docs().await?,
+            name
+        );
+        Ok(Self {
+            name,
+            location,
+            index: RwLock::new(index),
+            meta: Mutex::new(meta),
+            coverage: None,
+        })
+    }
+
+    /// The current version of the index. Requests should hold on to it for their duration, so
+    /// that a reload doesn't switch versions under them. The previous version is dropped once
+    /// the last request using it finishes.
+    pub fn index(&self) -> Arc<AirmailIndex> {
+        self.index.read().unwrap().clone()
+    }
+
+    /// Swap in a new version of the index if one has been published since it was loaded.
+    async fn reload_if_changed(&self) -> Result<()> {
+        let mut meta = self.meta.lock().await;
+        let latest = AirmailIndex::read_meta(&self.location).await?;
+        if latest == *meta {
+            return Ok(());
+        }
+        let location = self.location.clone();
+        let index = Arc::new(spawn_blocking(move || AirmailIndex::open(&location)).await??);
+        // Make sure the new version is usable before serving from it.
+        let num_docs = index.num_docs().await?;
+        *self.index.write().unwrap() = index;
+        *meta = latest;
+        info!(
+            "Reloaded index {}, which now has {} docs",
+            self.name, num_docs
+        );
+        Ok(())
+    }
+}
+
+/// Every index the service serves.
+#[derive(Clone)]
+pub struct Datasets(Arc<Vec<Dataset>>);
+
+/// Split an `--index` argument into its name and location. Unnamed indices are named after the
+/// last segment of their path or URL, e.g. `/data/europe/` is `europe`.
+fn parse_index_arg(arg: &str) -> (String, String) {
+    if let Some((name, location)) = arg.split_once('=') {
+        if !name.is_empty() && !name.contains(['/', ':']) {
+            return (name.to_string(), location.to_string());
+        }
+    }
+    let name = arg
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(arg)
+        .to_string();
+    (name, arg.to_string())
+}
+
+impl Datasets {
+    /// Open indices from `--index` arguments of the form `[name=]path-or-url`, with coverage
+    /// areas from `--coverage` arguments of the form `name=min_lng,min_lat,max_lng,max_lat`.
+    pub async fn open(index_args: &[String], coverage_args: &[String]) -> Result<Self> {
+        let mut datasets: Vec<Dataset> = Vec::new();
+        for arg in index_args {
+            let (name, location) = parse_index_arg(arg);
+            if datasets.iter().any(|dataset| dataset.name == name) {
+                return Err(anyhow!("More than one index is named {}", name));
+            }
+            datasets.push(Dataset::open(name, location).await?);
+        }
+        if datasets.is_empty() {
+            return Err(anyhow!("At least one index is required"));
+        }
+
+        for arg in coverage_args {
+            let (name, bbox) = arg
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Coverage must be name=bbox, got {}", arg))?;
+            let dataset = datasets
+                .iter_mut()
+                .find(|dataset| dataset.name == name)
+                .ok_or_else(|| anyhow!("Coverage given for unknown index {}", name))?;
+            dataset.coverage = Some(
+                parse_bbox(bbox)
+                    .map_err(|err| anyhow!("Invalid coverage for {}: {}", name, err))?,
+            );
+        }
+
+        Ok(Self(Arc::new(datasets)))
+    }
+
+    /// Check each index for a new version every `period`, and swap it in when there is one.
+    /// A version that fails to load is retried on the next check, and the old one is kept.
+    pub fn watch(&self, period: Duration) {
+        let datasets = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            // The first tick completes immediately, and the indices were only just loaded.
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                for dataset in datasets.iter() {
+                    if let Err(err) = dataset.reload_if_changed().await {
+                        warn!("Failed to reload index {}: {:#}", dataset.name, err);
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Dataset> {
+        self.0.iter()
+    }
+
+    /// The datasets a request should go to: the comma-separated `names` if given, otherwise
+    /// those covering `bbox`, otherwise all of them.
+    pub fn select(
+        &self,
+        names: Option<&str>,
+        bbox: Option<&Bbox>,
+    ) -> Result<Vec<&Dataset>, AirmailServiceError> {
+        if let Some(names) = names {
+            return names
+                .split(',')
+                .map(|name| {
+                    self.iter()
+                        .find(|dataset| dataset.name == name.trim())
+                        .ok_or_else(|| AirmailServiceError::UnknownDataset(name.to_string()))
+                })
+                .collect();
+        }
+        Ok(self
+            .iter()
+            .filter(|dataset| match (bbox, &dataset.coverage) {
+                (Some(bbox), Some(coverage)) => bbox.intersects(coverage),
+                _ => true,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_index_arg;
+
+    #[test]
+    fn test_parse_index_arg() {
+        assert_eq!(
+            parse_index_arg("europe=/data/europe"),
+            ("europe".to_string(), "/data/europe".to_string())
+        );
+        assert_eq!(
+            parse_index_arg("/data/addresses/"),
+            ("addresses".to_string(), "/data/addresses/".to_string())
+        );
+        assert_eq!(
+            parse_index_arg("https://example.com/north-america"),
+            (
+                "north-america".to_string(),
+                "https://example.com/north-america".to_string()
+            )
+        );
+    }
+}
@@ -1,10 +1,15 @@
+use airmail::error::AirmailError;
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
+    Json,
 };
 use log::warn;
+use serde_json::json;
 use thiserror::Error;
 
+use crate::metrics::observe_error;
+
 #[derive(Error, Debug)]
 #[allow(clippy::module_name_repetitions)]
 pub enum AirmailServiceError {
@@ -13,20 +18,101 @@ pub enum AirmailServiceError {
 
     #[error("failed to encode response")]
     SerdeEncodeError(#[from] serde_json::Error),
+
+    #[error("unknown dataset: `{0}`")]
+    UnknownDataset(String),
+
+    #[error("invalid bbox: {0}")]
+    InvalidBbox(String),
+
+    #[error("{name} `{value}` is out of range, it must be between -{limit} and {limit}")]
+    CoordinateOutOfRange {
+        name: &'static str,
+        value: f64,
+        limit: f64,
+    },
+
+    #[error("query is empty")]
+    EmptyQuery,
+
+    #[error("query is {length} characters long, the most allowed is {max}")]
+    QueryTooLong { length: usize, max: usize },
+
+    #[error("unknown parameter: `{0}`")]
+    UnknownParameter(String),
+
+    #[error("invalid parameter: {0}")]
+    InvalidParameter(String),
+
+    #[error("invalid batch: {0}")]
+    InvalidBatch(String),
+}
+
+impl AirmailServiceError {
+    /// The name of the variant, for metrics and error responses.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Self::InternalAnyhowError(_) => "InternalAnyhowError",
+            Self::SerdeEncodeError(_) => "SerdeEncodeError",
+            Self::UnknownDataset(_) => "UnknownDataset",
+            Self::InvalidBbox(_) => "InvalidBbox",
+            Self::CoordinateOutOfRange { .. } => "CoordinateOutOfRange",
+            Self::EmptyQuery => "EmptyQuery",
+            Self::QueryTooLong { .. } => "QueryTooLong",
+            Self::UnknownParameter(_) => "UnknownParameter",
+            Self::InvalidParameter(_) => "InvalidParameter",
+            Self::InvalidBatch(_) => "InvalidBatch",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InternalAnyhowError(_) | Self::SerdeEncodeError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Self::UnknownDataset(_)
+            | Self::InvalidBbox(_)
+            | Self::CoordinateOutOfRange { .. }
+            | Self::EmptyQuery
+            | Self::QueryTooLong { .. }
+            | Self::UnknownParameter(_)
+            | Self::InvalidParameter(_)
+            | Self::InvalidBatch(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// The JSON describing the error in responses, like
+    /// `{"error": "EmptyQuery", "message": "query is empty"}`.
+    pub fn body(&self) -> serde_json::Value {
+        let message = match self {
+            Self::InternalAnyhowError(e) => e.to_string(),
+            Self::SerdeEncodeError(e) => e.to_string(),
+            _ => self.to_string(),
+        };
+        json!({ "error": self.variant(), "message": message })
+    }
 }
 
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AirmailServiceError {
     fn into_response(self) -> Response {
-        match &self {
-            Self::InternalAnyhowError(e) => {
-                warn!("InternalAnyhowError: {:#}", self);
-                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        observe_error(&self);
+        let status = self.status();
+        if status.is_server_error() {
+            warn!("{}: {:#}", self.variant(), self);
+        }
+        (status, Json(self.body())).into_response()
+    }
+}
+
+impl From<AirmailError> for AirmailServiceError {
+    fn from(e: AirmailError) -> Self {
+        match e {
+            AirmailError::InvalidBbox(reason) => Self::InvalidBbox(reason),
+            AirmailError::CoordinateOutOfRange { name, value, limit } => {
+                Self::CoordinateOutOfRange { name, value, limit }
             }
-            Self::SerdeEncodeError(e) => {
-                warn!("SerdeEncodeError: {:#}", self);
-                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
-            } // _ => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response(),
+            e @ AirmailError::UnableToCount => Self::InternalAnyhowError(Box::new(e.into())),
         }
     }
 }
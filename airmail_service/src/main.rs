@@ -1,28 +1,55 @@
 #![forbid(unsafe_code)]
 #![warn(clippy::pedantic)]
+#![allow(clippy::non_std_lazy_statics)]
+
+#[macro_use]
+extern crate lazy_static;
 
 use std::future::IntoFuture;
-use std::sync::Arc;
+use std::time::Duration;
 
-use airmail::index::AirmailIndex;
-use anyhow::Result;
-use api::search;
-use axum::{http::HeaderValue, routing::get, Router};
+use airmail::{dictionaries::load_dictionary_dir, directory::RemoteFetchLogger};
+use anyhow::{anyhow, Result};
+use api::{health, parse, ready, search, status, ReadyState};
+use axum::{
+    http::HeaderValue,
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use batch::{batch, BatchState};
 use clap::Parser;
+use datasets::Datasets;
 use env_logger::Env;
-use log::{debug, info, warn};
+use log::{info, warn};
 use tokio::net::TcpListener;
 use tokio::select;
 use tower_http::cors::CorsLayer;
 
 mod api;
+mod batch;
+mod datasets;
 mod error;
+mod metrics;
+mod validation;
 
 #[derive(Debug, Parser)]
 struct Args {
-    /// The path to the index to load
-    #[arg(short, long, env = "AIRMAIL_INDEX")]
-    index: String,
+    /// The indices to load, as `[name=]path-or-url`. Unnamed indices are named after the last
+    /// segment of their path
+    #[arg(
+        short,
+        long,
+        env = "AIRMAIL_INDEX",
+        value_delimiter = ',',
+        required = true
+    )]
+    index: Vec<String>,
+
+    /// The area an index covers, as `name=min_lng,min_lat,max_lng,max_lat`. Searches with a
+    /// bbox outside of it skip the index
+    #[arg(long, env = "AIRMAIL_COVERAGE", value_delimiter = ';')]
+    coverage: Vec<String>,
 
     /// The address to bind to
     #[arg(short, long, env = "AIRMAIL_BIND", default_value = "127.0.0.1:3000")]
@@ -36,28 +63,74 @@ struct Args {
         default_value = "http://localhost:5173"
     )]
     cors: Option<Vec<String>>,
+
+    /// Extra directories of custom substitution dictionaries used to expand queries. These
+    /// should match the ones the index was built with.
+    #[arg(long, env = "AIRMAIL_DICTIONARIES", value_delimiter = ',')]
+    dictionaries: Vec<String>,
+
+    /// Query run by `/ready` to check that the index can serve searches
+    #[arg(long, env = "AIRMAIL_CANARY_QUERY", default_value = "main street")]
+    canary_query: String,
+
+    /// How often to check for new versions of the indices, in seconds. New versions are loaded
+    /// without interrupting searches. 0 disables reloading
+    #[arg(long, env = "AIRMAIL_RELOAD_INTERVAL", default_value_t = 30)]
+    reload_interval: u64,
+
+    /// How many queries of a `/batch` request are searched at once
+    #[arg(long, env = "AIRMAIL_BATCH_PARALLELISM", default_value_t = 8)]
+    batch_parallelism: usize,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    RemoteFetchLogger::init(
+        env_logger::Builder::from_env(Env::default().default_filter_or("info")).build(),
+    )?;
     let args = Args::parse();
 
-    debug!("Loading index from {}", args.index);
-    let index = if args.index.starts_with("http") {
-        Arc::new(AirmailIndex::new_remote(&args.index)?)
-    } else {
-        Arc::new(AirmailIndex::new(&args.index)?)
-    };
+    for dictionary_dir in &args.dictionaries {
+        load_dictionary_dir(dictionary_dir.as_ref()).map_err(|err| {
+            anyhow!(
+                "Failed to load dictionaries from {}: {}",
+                dictionary_dir,
+                err
+            )
+        })?;
+    }
+
+    let datasets = Datasets::open(&args.index, &args.coverage).await?;
+    if args.reload_interval > 0 {
+        datasets.watch(Duration::from_secs(args.reload_interval));
+    }
 
     let mut cors = CorsLayer::new();
     for origin in args.cors.unwrap_or_default() {
         cors = cors.allow_origin(origin.parse::<HeaderValue>()?);
     }
 
-    info!("Loaded {} docs from index", index.num_docs().await?);
     let app = Router::new()
-        .route("/search", get(search).with_state(index))
+        .route("/search", get(search).with_state(datasets.clone()))
+        .route("/parse", get(parse).with_state(datasets.clone()))
+        .route("/status", get(status).with_state(datasets.clone()))
+        .route(
+            "/batch",
+            post(batch).with_state(BatchState {
+                datasets: datasets.clone(),
+                parallelism: args.batch_parallelism.max(1),
+            }),
+        )
+        .route(
+            "/ready",
+            get(ready).with_state(ReadyState {
+                datasets,
+                canary_query: args.canary_query,
+            }),
+        )
+        .route("/health", get(health))
+        .route("/metrics", get(metrics::metrics))
+        .route_layer(middleware::from_fn(metrics::track))
         .layer(cors);
 
     info!("Listening at: {}/search?q=query", args.bind);
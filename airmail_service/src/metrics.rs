@@ -0,0 +1,180 @@
+use std::time::Instant;
+
+use airmail::directory::remote_stats;
+use axum::{
+    extract::{MatchedPath, Request},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    core::Collector, exponential_buckets, Encoder, Gauge, Histogram, HistogramOpts, HistogramVec,
+    IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+use crate::error::AirmailServiceError;
+
+lazy_static! {
+    static ref METRICS: Metrics = Metrics::new();
+}
+
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    results: Histogram,
+    zero_results: IntCounter,
+    errors: IntCounterVec,
+    remote_requests: IntCounterVec,
+    remote_cache_hits: IntCounter,
+    remote_cache_hit_ratio: Gauge,
+    remote_bytes_fetched: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let metrics = Self {
+            registry: Registry::new(),
+            requests: IntCounterVec::new(
+                Opts::new("airmail_http_requests_total", "HTTP requests served."),
+                &["endpoint", "status"],
+            )
+            .unwrap(),
+            latency: HistogramVec::new(
+                HistogramOpts::new(
+                    "airmail_http_request_duration_seconds",
+                    "Time taken to serve HTTP requests.",
+                )
+                .buckets(exponential_buckets(0.001, 2.0, 14).unwrap()),
+                &["endpoint"],
+            )
+            .unwrap(),
+            results: Histogram::with_opts(
+                HistogramOpts::new("airmail_search_results", "Results returned per search.")
+                    .buckets(vec![0.0, 1.0, 2.0, 5.0, 10.0]),
+            )
+            .unwrap(),
+            zero_results: IntCounter::new(
+                "airmail_search_zero_results_total",
+                "Searches that returned no results. Divide by airmail_search_results_count for \
+                 the zero-result rate.",
+            )
+            .unwrap(),
+            errors: IntCounterVec::new(
+                Opts::new("airmail_errors_total", "Errors returned, by kind."),
+                &["variant"],
+            )
+            .unwrap(),
+            remote_requests: IntCounterVec::new(
+                Opts::new(
+                    "airmail_remote_requests_total",
+                    "Requests to remote indices, by what they fetched.",
+                ),
+                &["kind"],
+            )
+            .unwrap(),
+            remote_cache_hits: IntCounter::new(
+                "airmail_remote_cache_hits_total",
+                "Remote index page faults, file opens and whole-file reads served without a \
+                 request.",
+            )
+            .unwrap(),
+            remote_cache_hit_ratio: Gauge::new(
+                "airmail_remote_cache_hit_ratio",
+                "Fraction of remote index lookups served without a request.",
+            )
+            .unwrap(),
+            remote_bytes_fetched: IntCounter::new(
+                "airmail_remote_fetched_bytes_total",
+                "Bytes fetched from remote indices.",
+            )
+            .unwrap(),
+        };
+
+        let collectors: [Box<dyn Collector>; 9] = [
+            Box::new(metrics.requests.clone()),
+            Box::new(metrics.latency.clone()),
+            Box::new(metrics.results.clone()),
+            Box::new(metrics.zero_results.clone()),
+            Box::new(metrics.errors.clone()),
+            Box::new(metrics.remote_requests.clone()),
+            Box::new(metrics.remote_cache_hits.clone()),
+            Box::new(metrics.remote_cache_hit_ratio.clone()),
+            Box::new(metrics.remote_bytes_fetched.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).unwrap();
+        }
+        metrics
+    }
+
+    /// Catch the remote counters up with the index's, which are kept separately.
+    fn update_remote(&self) {
+        let stats = remote_stats();
+        let catch_up = |counter: &IntCounter, value: u64| {
+            counter.inc_by(value.saturating_sub(counter.get()));
+        };
+        catch_up(
+            &self.remote_requests.with_label_values(&["length"]),
+            stats.length_requests,
+        );
+        catch_up(
+            &self.remote_requests.with_label_values(&["atomic_read"]),
+            stats.atomic_read_requests,
+        );
+        catch_up(
+            &self.remote_requests.with_label_values(&["chunk"]),
+            stats.chunk_requests,
+        );
+        catch_up(&self.remote_cache_hits, stats.cache_hits);
+        catch_up(&self.remote_bytes_fetched, stats.bytes_fetched);
+        self.remote_cache_hit_ratio.set(stats.cache_hit_ratio());
+    }
+}
+
+/// Record how many results a search returned.
+pub fn observe_search(results: usize) {
+    #[allow(clippy::cast_precision_loss)]
+    METRICS.results.observe(results as f64);
+    if results == 0 {
+        METRICS.zero_results.inc();
+    }
+}
+
+/// Record an error returned from a handler.
+pub fn observe_error(error: &AirmailServiceError) {
+    METRICS.errors.with_label_values(&[error.variant()]).inc();
+}
+
+/// Middleware counting requests and timing them by route.
+pub async fn track(request: Request, next: Next) -> Response {
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unknown", MatchedPath::as_str)
+        .to_string();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    METRICS
+        .latency
+        .with_label_values(&[&endpoint])
+        .observe(start.elapsed().as_secs_f64());
+    METRICS
+        .requests
+        .with_label_values(&[&endpoint, response.status().as_str()])
+        .inc();
+    response
+}
+
+pub async fn metrics() -> Result<impl IntoResponse, AirmailServiceError> {
+    METRICS.update_remote();
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder
+        .encode(&METRICS.registry.gather(), &mut body)
+        .map_err(anyhow::Error::from)?;
+    Ok((
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        body,
+    ))
+}
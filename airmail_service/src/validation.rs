@@ -0,0 +1,76 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use serde::de::DeserializeOwned;
+
+use crate::error::AirmailServiceError;
+
+/// The longest query accepted, in characters. Longer ones are almost certainly not addresses,
+/// and are expensive to plan.
+pub const MAX_QUERY_LENGTH: usize = 256;
+
+/// Query parameters a handler accepts.
+pub trait Parameters: DeserializeOwned {
+    /// The names of every parameter, so that misspelled ones are rejected rather than ignored.
+    const NAMES: &'static [&'static str];
+
+    /// Check the parameters beyond what deserializing them does.
+    fn validate(&self) -> Result<(), AirmailServiceError>;
+}
+
+/// Extracts and validates query parameters, rejecting the request with a 400 if they're invalid.
+pub struct Validated<T>(pub T);
+
+#[async_trait]
+impl<T: Parameters, S: Send + Sync> FromRequestParts<S> for Validated<T> {
+    type Rejection = AirmailServiceError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Query(pairs) = Query::<Vec<(String, String)>>::try_from_uri(&parts.uri)
+            .map_err(|rejection| AirmailServiceError::InvalidParameter(rejection.body_text()))?;
+        if let Some((name, _)) = pairs.iter().find(|(name, _)| !T::NAMES.contains(&&**name)) {
+            return Err(AirmailServiceError::UnknownParameter(name.clone()));
+        }
+        let Query(params) = Query::<T>::try_from_uri(&parts.uri)
+            .map_err(|rejection| AirmailServiceError::InvalidParameter(rejection.body_text()))?;
+        params.validate()?;
+        Ok(Self(params))
+    }
+}
+
+/// Check that a query has something to search for, and isn't unreasonably long.
+pub fn validate_query(query: &str) -> Result<(), AirmailServiceError> {
+    if query.trim().is_empty() {
+        return Err(AirmailServiceError::EmptyQuery);
+    }
+    let length = query.chars().count();
+    if length > MAX_QUERY_LENGTH {
+        return Err(AirmailServiceError::QueryTooLong {
+            length,
+            max: MAX_QUERY_LENGTH,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::AirmailServiceError;
+
+    use super::validate_query;
+
+    #[test]
+    fn test_validate_query() {
+        assert!(validate_query("425 Harvard Ave").is_ok());
+        assert!(matches!(
+            validate_query("  "),
+            Err(AirmailServiceError::EmptyQuery)
+        ));
+        assert!(matches!(
+            validate_query(&"a".repeat(1000)),
+            Err(AirmailServiceError::QueryTooLong { length: 1000, .. })
+        ));
+    }
+}